@@ -0,0 +1,129 @@
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tar::{Archive, EntryType};
+
+use crate::content::ContentSniffer;
+use crate::hash::hash_reader_inspecting;
+use crate::manifest::sort_entries;
+use crate::options::ProcessOptions;
+use crate::paths::normalize_path;
+use crate::patterns::{IgnoreMatcher, IncludeMatcher};
+use crate::scan::is_sampled;
+use crate::{FileHash, KushnError, KushnResult};
+
+/// Hashes the regular files inside a tar archive without extracting it, keyed by
+/// their path inside the archive. Compressed archives are read by wrapping the
+/// reader in a decoder first. Links and other special entries are skipped.
+///
+/// Ignore and include patterns, sampling, the age and size filters, the file
+/// limits, path binding, content-type detection, ownership, file metadata, the
+/// encoding, truncation, the `./` prefix and sorting apply as for directory
+/// scans, with ages, ownership and metadata coming from the tar headers. Nested
+/// ignore files, excluded files and the settings that need files on disk, such
+/// as symlink policies, decompression, the cache or content deduplication, are
+/// not used.
+pub fn process_tar<R: Read>(reader: R, options: &ProcessOptions) -> KushnResult<Vec<FileHash>> {
+    options.check_truncate()?;
+    let ignore = IgnoreMatcher::new(&options.ignore, !options.case_insensitive)?;
+    let include = IncludeMatcher::new(&options.include, !options.case_insensitive)?;
+    let scan_started = SystemTime::now();
+    let mut archive = Archive::new(reader);
+    let mut entries = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !matches!(
+            entry.header().entry_type(),
+            EntryType::Regular | EntryType::Continuous
+        ) {
+            continue;
+        }
+        let entry_path = entry.path()?;
+        if ignore.is_path_ignored(&entry_path) {
+            continue;
+        }
+        let path_string = normalize_path(&entry_path);
+        if !include.is_normalized_file_included(&path_string) {
+            continue;
+        }
+        if let Some((fraction, seed)) = options.sample {
+            if !is_sampled(&path_string, fraction, seed) {
+                continue;
+            }
+        }
+        let mtime = entry
+            .header()
+            .mtime()
+            .ok()
+            .map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime));
+        if let (Some(max_age), Some(modified)) = (options.max_age, mtime) {
+            if scan_started.duration_since(modified).unwrap_or_default() > max_age {
+                continue;
+            }
+        }
+        let size = entry.size();
+        if options.min_size.is_some_and(|min_size| size < min_size)
+            || options.max_size.is_some_and(|max_size| size > max_size)
+        {
+            continue;
+        }
+
+        if let Some(limit) = options.max_file_size {
+            if size > limit {
+                return Err(KushnError::FileTooLarge {
+                    path: Path::new(&path_string).to_path_buf(),
+                    size,
+                    limit,
+                });
+            }
+        }
+        if let Some(limit) = options.max_files {
+            if entries.len() >= limit {
+                return Err(KushnError::FileLimitExceeded { limit });
+            }
+        }
+
+        let bound_path = options.path_binding.then_some(path_string.as_str());
+        let mut sniffer = ContentSniffer::default();
+        let hash = hash_reader_inspecting(&mut entry, options.algorithm, bound_path, |chunk| {
+            if options.content_type {
+                sniffer.update(chunk);
+            }
+        })?;
+
+        let path_string = if options.dot_prefix {
+            format!("./{}", path_string)
+        } else {
+            path_string
+        };
+        let header = entry.header();
+        let (uid, gid) = if options.ownership {
+            (
+                header.uid().ok().and_then(|uid| u32::try_from(uid).ok()),
+                header.gid().ok().and_then(|gid| u32::try_from(gid).ok()),
+            )
+        } else {
+            (None, None)
+        };
+        let (size, modified) = if options.file_metadata {
+            (Some(size), mtime)
+        } else {
+            (None, None)
+        };
+        entries.push(FileHash {
+            content_type: options.content_type.then(|| sniffer.content_type()),
+            uid,
+            gid,
+            algorithm: options.recorded_algorithm(),
+            size,
+            modified,
+            ..FileHash::new(path_string, options.written_hash(hash))
+        });
+    }
+    if options.sorted {
+        sort_entries(&mut entries);
+    }
+    Ok(entries)
+}
@@ -0,0 +1,41 @@
+use std::io;
+use std::path::Path;
+
+use tokio::task;
+
+use crate::hash::{calculate_hash_reader, HashAlgorithm};
+use crate::options::ProcessOptions;
+use crate::scan::process_directory_with;
+use crate::{FileHash, KushnError, KushnResult};
+
+/// Runs `work` on tokio's blocking pool. A panic in it is resumed on the caller.
+async fn run_blocking<T, F>(work: F) -> KushnResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> KushnResult<T> + Send + 'static,
+{
+    match task::spawn_blocking(work).await {
+        Ok(result) => result,
+        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+        Err(err) => Err(KushnError::Io(io::Error::other(err))),
+    }
+}
+
+/// Async counterpart of [`calculate_file_hash`](crate::calculate_file_hash). The
+/// file is opened with `tokio::fs` and digested on the blocking pool, so neither
+/// step holds up the runtime's worker threads.
+pub async fn calculate_file_hash_async<P: AsRef<Path>>(file_path: P) -> KushnResult<String> {
+    let file = tokio::fs::File::open(file_path).await?.into_std().await;
+    run_blocking(move || Ok(calculate_hash_reader(file, HashAlgorithm::Sha256)?)).await
+}
+
+/// Async counterpart of [`process_directory_with`], running the whole scan on
+/// the blocking pool.
+pub async fn process_directory_async<P: AsRef<Path>>(
+    directory_path: P,
+    options: &ProcessOptions,
+) -> KushnResult<Vec<FileHash>> {
+    let directory_path = directory_path.as_ref().to_path_buf();
+    let options = options.clone();
+    run_blocking(move || process_directory_with(directory_path, &options)).await
+}
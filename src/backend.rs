@@ -0,0 +1,49 @@
+use crate::hash::HashAlgorithm;
+
+/// CPU features that the hash implementations check for at runtime, and whether
+/// this machine has them. Empty on architectures where none are checked.
+pub fn hash_cpu_features() -> Vec<(&'static str, bool)> {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        vec![
+            ("sse2", is_x86_feature_detected!("sse2")),
+            ("ssse3", is_x86_feature_detected!("ssse3")),
+            ("sse4.1", is_x86_feature_detected!("sse4.1")),
+            ("sha", is_x86_feature_detected!("sha")),
+            ("avx2", is_x86_feature_detected!("avx2")),
+            ("avx512f", is_x86_feature_detected!("avx512f")),
+            ("avx512vl", is_x86_feature_detected!("avx512vl")),
+            ("pclmulqdq", is_x86_feature_detected!("pclmulqdq")),
+        ]
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        vec![("crc", std::arch::is_aarch64_feature_detected!("crc"))]
+    }
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        Vec::new()
+    }
+}
+
+/// The implementation `algorithm` runs on this machine, following the same
+/// checks as the hash crates: `SHA-NI` for SHA-256 and SHA-1 on x86 CPUs with
+/// the SHA extensions, the widest SIMD set for BLAKE3, and so on. `generic`
+/// means plain Rust code without hardware acceleration.
+pub fn hash_backend(algorithm: HashAlgorithm) -> &'static str {
+    let features = hash_cpu_features();
+    let has = |names: &[&str]| names.iter().all(|name| features.contains(&(*name, true)));
+    match algorithm {
+        HashAlgorithm::Sha256 | HashAlgorithm::Sha1 if has(&["sha", "sse2", "ssse3", "sse4.1"]) => {
+            "SHA-NI"
+        }
+        HashAlgorithm::Sha512 if has(&["avx2"]) => "AVX2",
+        HashAlgorithm::Blake3 if has(&["avx512f", "avx512vl"]) => "AVX-512",
+        HashAlgorithm::Blake3 if has(&["avx2"]) => "AVX2",
+        HashAlgorithm::Blake3 if has(&["sse4.1"]) => "SSE4.1",
+        HashAlgorithm::Blake3 if has(&["sse2"]) => "SSE2",
+        HashAlgorithm::Crc32 if has(&["pclmulqdq", "sse2", "ssse3", "sse4.1"]) => "PCLMULQDQ",
+        HashAlgorithm::Crc32 if has(&["crc"]) => "ARMv8 CRC",
+        _ => "generic",
+    }
+}
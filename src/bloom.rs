@@ -0,0 +1,77 @@
+use bloomfilter::Bloom;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::hash::{decode_hex, encode_hex};
+use crate::{FileHash, KushnError, KushnResult};
+
+// Fixed so that filters built anywhere agree on their hash functions.
+const BLOOM_SEED: [u8; 32] = *b"kushn content hash bloom filter!";
+
+/// Compact, probabilistic set of the content hashes of a manifest.
+///
+/// [`bloom_contains`] never reports a hash that was inserted as absent, but may
+/// report an absent hash as present with roughly the false-positive rate the
+/// filter was built for. A positive answer therefore means "probably in the
+/// manifest" and has to be confirmed against the full manifest when it matters.
+///
+/// Serializes to a hex string, to be shipped alongside or instead of a manifest.
+pub struct BloomFilter {
+    bloom: Bloom<str>,
+}
+
+impl std::fmt::Debug for BloomFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BloomFilter")
+            .field("bits", &self.bloom.len())
+            .field("hash_functions", &self.bloom.number_of_hash_functions())
+            .finish()
+    }
+}
+
+impl BloomFilter {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bloom.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> KushnResult<Self> {
+        let bloom = Bloom::from_bytes(bytes)
+            .map_err(|err| KushnError::InvalidBloomFilter(err.to_owned()))?;
+        Ok(BloomFilter { bloom })
+    }
+}
+
+impl Serialize for BloomFilter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode_hex(&self.to_bytes()))
+    }
+}
+
+impl<'de> Deserialize<'de> for BloomFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let bytes = decode_hex(&encoded)
+            .ok_or_else(|| serde::de::Error::custom("bloom filter is not valid hex"))?;
+        BloomFilter::from_bytes(bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Builds a filter over the hashes of `entries`, sized for a false-positive rate
+/// of `fp_rate`, which has to lie strictly between 0 and 1.
+pub fn build_hash_bloom(entries: &[FileHash], fp_rate: f64) -> KushnResult<BloomFilter> {
+    if !(fp_rate > 0.0 && fp_rate < 1.0) {
+        return Err(KushnError::InvalidBloomFilter(format!(
+            "false-positive rate {} is not between 0 and 1",
+            fp_rate
+        )));
+    }
+    let mut bloom = Bloom::new_for_fp_rate_with_seed(entries.len().max(1), fp_rate, &BLOOM_SEED)
+        .map_err(|err| KushnError::InvalidBloomFilter(err.to_owned()))?;
+    for entry in entries {
+        bloom.set(entry.hash.to_ascii_lowercase().as_str());
+    }
+    Ok(BloomFilter { bloom })
+}
+
+pub fn bloom_contains(filter: &BloomFilter, hash: &str) -> bool {
+    filter.bloom.check(hash.to_ascii_lowercase().as_str())
+}
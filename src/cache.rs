@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+
+use crate::KushnResult;
+
+pub const DEFAULT_CACHE_FILE: &str = ".kushn_cache.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey {
+    dev: u64,
+    ino: u64,
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+}
+
+impl CacheKey {
+    #[cfg(unix)]
+    pub(crate) fn from_metadata(metadata: &Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        use std::time::UNIX_EPOCH;
+
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(CacheKey {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            size: metadata.len(),
+            mtime_secs: modified.as_secs(),
+            mtime_nanos: modified.subsec_nanos(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn from_metadata(_metadata: &Metadata) -> Option<Self> {
+        None
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheRecord {
+    dev: u64,
+    ino: u64,
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    hash: String,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<(u64, u64), (CacheKey, String)>,
+    hits: usize,
+    misses: usize,
+}
+
+/// Hash cache keyed by `(dev, ino)` and invalidated whenever a file's size or
+/// mtime changes. Clones share the same store, so a handle kept by the caller
+/// observes what a scan recorded. Only available on Unix; elsewhere every lookup
+/// misses.
+#[derive(Debug, Clone, Default)]
+pub struct CacheStore {
+    state: Arc<Mutex<CacheState>>,
+    path: Option<PathBuf>,
+}
+
+impl CacheStore {
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Opens a file-backed store, loading previous entries if the file exists.
+    /// Scans using this store write it back when they finish.
+    pub fn file<P: AsRef<Path>>(path: P) -> KushnResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut state = CacheState::default();
+        if path.exists() {
+            let records: Vec<CacheRecord> = serde_json::from_str(&fs::read_to_string(&path)?)?;
+            for record in records {
+                let key = CacheKey {
+                    dev: record.dev,
+                    ino: record.ino,
+                    size: record.size,
+                    mtime_secs: record.mtime_secs,
+                    mtime_nanos: record.mtime_nanos,
+                };
+                state.entries.insert((key.dev, key.ino), (key, record.hash));
+            }
+        }
+        Ok(CacheStore {
+            state: Arc::new(Mutex::new(state)),
+            path: Some(path),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub(crate) fn lookup(&self, key: &CacheKey) -> Option<String> {
+        let mut state = self.lock();
+        let hash = match state.entries.get(&(key.dev, key.ino)) {
+            Some((cached_key, hash)) if cached_key == key => Some(hash.clone()),
+            _ => None,
+        };
+        if hash.is_some() {
+            state.hits += 1;
+        } else {
+            state.misses += 1;
+        }
+        hash
+    }
+
+    pub(crate) fn insert(&self, key: CacheKey, hash: String) {
+        self.lock().entries.insert((key.dev, key.ino), (key, hash));
+    }
+
+    pub fn hits(&self) -> usize {
+        self.lock().hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.lock().misses
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn save(&self) -> KushnResult<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let records: Vec<CacheRecord> = self
+            .lock()
+            .entries
+            .values()
+            .map(|(key, hash)| CacheRecord {
+                dev: key.dev,
+                ino: key.ino,
+                size: key.size,
+                mtime_secs: key.mtime_secs,
+                mtime_nanos: key.mtime_nanos,
+                hash: hash.clone(),
+            })
+            .collect();
+        fs::write(path, serde_json::to_string(&records)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use std::io::{self, Read};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::paths::open_file;
+use crate::{KushnError, KushnResult};
+
+pub const MIN_AVG_CHUNK_SIZE: usize = 64;
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64, so the table is fixed without shipping 256 literals.
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x6b75_7368_6e5f_6364;
+    let mut index = 0;
+    while index < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[index] = z ^ (z >> 31);
+        index += 1;
+    }
+    table
+}
+
+static GEAR: [u64; 256] = gear_table();
+
+fn high_bits_mask(bits: u32) -> u64 {
+    u64::MAX << (64 - bits)
+}
+
+fn finish_chunk(chunk: &mut Vec<u8>, chunks: &mut Vec<(u64, String)>) {
+    let hash = Sha256::digest(chunk.as_slice());
+    chunks.push((chunk.len() as u64, format!("{:x}", hash)));
+    chunk.clear();
+}
+
+/// Splits a file into content-defined chunks and returns `(length, sha256)` for
+/// each, in file order. Boundaries are chosen by a gear rolling hash as in
+/// FastCDC, with normalized chunking around `avg_chunk_size` (rounded up to a
+/// power of two): a stricter mask below the average and a looser one above it,
+/// chunks between a quarter and eight times the average. Boundaries depend only
+/// on nearby content, so bytes inserted early in a file only change the chunks
+/// around the insertion.
+pub fn chunk_file_cdc<P: AsRef<Path>>(
+    file_path: P,
+    avg_chunk_size: usize,
+) -> KushnResult<Vec<(u64, String)>> {
+    if avg_chunk_size < MIN_AVG_CHUNK_SIZE {
+        return Err(KushnError::InvalidSize(format!(
+            "average chunk size {} is below {}",
+            avg_chunk_size, MIN_AVG_CHUNK_SIZE
+        )));
+    }
+    let Some((avg, max)) = avg_chunk_size
+        .checked_next_power_of_two()
+        .and_then(|avg| Some((avg, avg.checked_mul(8)?)))
+    else {
+        return Err(KushnError::InvalidSize(format!(
+            "average chunk size {} is too large",
+            avg_chunk_size
+        )));
+    };
+    let bits = avg.trailing_zeros();
+    let min = avg / 4;
+    let (mask_small, mask_large) = (high_bits_mask(bits + 1), high_bits_mask(bits - 1));
+
+    let mut file = open_file(file_path)?;
+    let mut chunks = Vec::new();
+    // Grows to the largest chunk met rather than `max` up front, which may be
+    // far more than the file.
+    let mut chunk = Vec::new();
+    let mut fingerprint = 0u64;
+    let mut buffer = [0u8; 8192];
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        for &byte in &buffer[..read] {
+            chunk.push(byte);
+            fingerprint = (fingerprint << 1).wrapping_add(GEAR[byte as usize]);
+            let len = chunk.len();
+            if len < min {
+                continue;
+            }
+            let mask = if len < avg { mask_small } else { mask_large };
+            if fingerprint & mask == 0 || len >= max {
+                finish_chunk(&mut chunk, &mut chunks);
+                fingerprint = 0;
+            }
+        }
+    }
+    if !chunk.is_empty() {
+        finish_chunk(&mut chunk, &mut chunks);
+    }
+    Ok(chunks)
+}
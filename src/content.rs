@@ -0,0 +1,25 @@
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+const SNIFF_LEN: usize = 8192;
+
+/// Keeps the leading bytes seen during the hash read so the content type can be
+/// detected from magic bytes without reading the file a second time.
+#[derive(Debug, Default)]
+pub(crate) struct ContentSniffer {
+    head: Vec<u8>,
+}
+
+impl ContentSniffer {
+    pub(crate) fn update(&mut self, chunk: &[u8]) {
+        let remaining = SNIFF_LEN.saturating_sub(self.head.len());
+        self.head
+            .extend_from_slice(&chunk[..remaining.min(chunk.len())]);
+    }
+
+    pub(crate) fn content_type(&self) -> String {
+        infer::get(&self.head)
+            .map(|kind| kind.mime_type())
+            .unwrap_or(DEFAULT_CONTENT_TYPE)
+            .to_owned()
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::options::ProcessOptions;
+use crate::scan::process_directory_with;
+use crate::{FileHash, KushnResult};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DirectoryDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl DirectoryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Manifests written elsewhere may use `\\` separators or a leading `./`.
+fn normalize_entry_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    match path.strip_prefix("./") {
+        Some(stripped) => stripped.to_owned(),
+        None => path,
+    }
+}
+
+/// Paths added in, removed from or changed in `new` relative to `old`, e.g. two
+/// manifests of the same tree taken at different times. Paths are compared
+/// after normalizing separators and a leading `./`.
+pub fn diff_manifests(old: &[FileHash], new: &[FileHash]) -> DirectoryDiff {
+    let mut old_hashes: HashMap<String, &str> = old
+        .iter()
+        .map(|entry| (normalize_entry_path(&entry.path), entry.hash.as_str()))
+        .collect();
+
+    let mut diff = DirectoryDiff::default();
+    for entry in new {
+        let path = normalize_entry_path(&entry.path);
+        match old_hashes.remove(&path) {
+            Some(hash) if hash == entry.hash => {}
+            Some(_) => diff.changed.push(path),
+            None => diff.added.push(path),
+        }
+    }
+    diff.removed = old_hashes.into_keys().collect();
+
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed.sort();
+    diff
+}
+
+/// Scans both trees and reports paths added in, removed from or changed in `dir_b`
+/// relative to `dir_a`, without writing intermediate manifests.
+pub fn compare_directories<P: AsRef<Path>, Q: AsRef<Path>>(
+    dir_a: P,
+    dir_b: Q,
+    options: &ProcessOptions,
+) -> KushnResult<DirectoryDiff> {
+    let entries_a = process_directory_with(dir_a, options)?;
+    let entries_b = process_directory_with(dir_b, options)?;
+    Ok(diff_manifests(&entries_a, &entries_b))
+}
+
+pub fn content_difference<P: AsRef<Path>, Q: AsRef<Path>>(
+    dir_a: P,
+    dir_b: Q,
+    options: &ProcessOptions,
+) -> KushnResult<Vec<String>> {
+    let entries_a = process_directory_with(dir_a, options)?;
+    let entries_b = process_directory_with(dir_b, options)?;
+
+    let hashes_b: HashSet<&str> = entries_b.iter().map(|entry| entry.hash.as_str()).collect();
+    let mut unique: Vec<String> = entries_a
+        .into_iter()
+        .filter(|entry| !hashes_b.contains(entry.hash.as_str()))
+        .map(|entry| entry.path)
+        .collect();
+    unique.sort();
+    Ok(unique)
+}
+
+/// Entries whose `(path, hash)` pair appears in exactly one of the two manifests,
+/// sorted by path.
+pub fn symmetric_difference(a: &[FileHash], b: &[FileHash]) -> Vec<FileHash> {
+    let pairs_a: HashSet<(&str, &str)> = a.iter().map(path_hash_pair).collect();
+    let pairs_b: HashSet<(&str, &str)> = b.iter().map(path_hash_pair).collect();
+
+    let mut difference: Vec<FileHash> = a
+        .iter()
+        .filter(|entry| !pairs_b.contains(&path_hash_pair(entry)))
+        .chain(
+            b.iter()
+                .filter(|entry| !pairs_a.contains(&path_hash_pair(entry))),
+        )
+        .cloned()
+        .collect();
+    difference.sort_by(|x, y| x.path.cmp(&y.path).then_with(|| x.hash.cmp(&y.hash)));
+    difference
+}
+
+fn path_hash_pair(entry: &FileHash) -> (&str, &str) {
+    (entry.path.as_str(), entry.hash.as_str())
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::hash::calculate_file_hash_with;
+use crate::options::ProcessOptions;
+use crate::paths::normalize_path;
+use crate::patterns::IgnoreMatcher;
+use crate::walk::walk;
+use crate::{FileHash, KushnResult};
+
+/// Relative paths of the files under `directory_path` whose content is identical
+/// to `reference`, sorted. The reference is hashed once, and files whose size
+/// differs from it are never opened. The reference itself is not reported when it
+/// lies inside the tree. Paths that cannot be read are skipped when the options
+/// allow continuing on errors.
+pub fn find_copies_of<P: AsRef<Path>, Q: AsRef<Path>>(
+    directory_path: P,
+    reference: Q,
+    options: &ProcessOptions,
+) -> KushnResult<Vec<String>> {
+    let directory_path = directory_path.as_ref();
+    let reference_size = fs::metadata(reference.as_ref())?.len();
+    let reference_hash = calculate_file_hash_with(reference.as_ref(), options.algorithm)?;
+    let reference_canonical = fs::canonicalize(reference.as_ref()).ok();
+    let ignore = IgnoreMatcher::new(&options.ignore, !options.case_insensitive)?;
+
+    let walker = walk(directory_path, options, |path| {
+        let relative_path = path.strip_prefix(directory_path).unwrap_or(path);
+        ignore.is_dir_ignored(relative_path)
+    });
+
+    let mut copies = Vec::new();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) if options.continue_on_error => continue,
+            Err((_, err)) => return Err(err),
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let path = entry.path();
+        let relative_path = path.strip_prefix(directory_path).unwrap_or(path);
+        let path_string = normalize_path(relative_path);
+        if ignore.is_normalized_file_ignored(&path_string) {
+            continue;
+        }
+
+        let hash = entry.metadata().and_then(|metadata| {
+            if metadata.len() != reference_size
+                || fs::canonicalize(path).ok() == reference_canonical
+            {
+                return Ok(None);
+            }
+            calculate_file_hash_with(path, options.algorithm).map(Some)
+        });
+        match hash {
+            Ok(Some(hash)) if hash == reference_hash => copies.push(path_string),
+            Ok(_) => {}
+            Err(_) if options.continue_on_error => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    copies.sort();
+    Ok(copies)
+}
+
+/// Paths of the entries sharing a hash, for every hash shared by two or more
+/// entries. Paths are sorted within each group and groups by their first path.
+pub fn find_duplicates(entries: &[FileHash]) -> Vec<Vec<String>> {
+    let mut by_hash: HashMap<&str, Vec<String>> = HashMap::new();
+    for entry in entries.iter().filter(|entry| !entry.is_dir) {
+        by_hash
+            .entry(entry.hash.as_str())
+            .or_default()
+            .push(entry.path.clone());
+    }
+    let mut groups: Vec<Vec<String>> = by_hash
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .map(|mut paths| {
+            paths.sort();
+            paths
+        })
+        .collect();
+    groups.sort();
+    groups
+}
@@ -0,0 +1,193 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum KushnError {
+    Io(io::Error),
+    Walk(walkdir::Error),
+    Pattern(glob::PatternError),
+    Json(serde_json::Error),
+    FileLimitExceeded {
+        limit: usize,
+    },
+    DirLimitExceeded {
+        limit: usize,
+    },
+    FileTooLarge {
+        path: PathBuf,
+        size: u64,
+        limit: u64,
+    },
+    InvalidSize(String),
+    InvalidSumsLine {
+        line: usize,
+    },
+    SymlinkLoop {
+        path: PathBuf,
+    },
+    InvalidManifest(String),
+    DuplicatePath(String),
+    UnknownAlgorithm(String),
+    InvalidBloomFilter(String),
+    IgnoreIncludeCycle {
+        path: PathBuf,
+    },
+    OutsideBaseDir {
+        path: PathBuf,
+        base: PathBuf,
+    },
+    #[cfg(feature = "http")]
+    Http(reqwest::Error),
+    NoFilesFound {
+        path: PathBuf,
+    },
+    /// An entry path with no more than `count` components, which
+    /// [`strip_components`](crate::strip_components) was asked to strip.
+    TooFewComponents {
+        path: String,
+        count: usize,
+    },
+    /// A command-line argument that could not be used, with the message to show.
+    InvalidArgument(String),
+    /// `source` occurred while working on `path`.
+    File {
+        path: PathBuf,
+        source: Box<KushnError>,
+    },
+}
+
+pub type KushnResult<T> = Result<T, KushnError>;
+
+impl KushnError {
+    /// Attaches the file or directory the error is about, for messages that
+    /// would otherwise not say which one failed.
+    pub fn with_path<P: Into<PathBuf>>(self, path: P) -> Self {
+        KushnError::File {
+            path: path.into(),
+            source: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for KushnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KushnError::Io(err) => write!(f, "I/O error: {}", err),
+            KushnError::Walk(err) => write!(f, "Failed to walk directory: {}", err),
+            KushnError::Pattern(err) => write!(f, "Invalid ignore pattern: {}", err),
+            KushnError::Json(err) => write!(f, "JSON error: {}", err),
+            KushnError::FileLimitExceeded { limit } => {
+                write!(f, "Scan exceeded the limit of {} files", limit)
+            }
+            KushnError::DirLimitExceeded { limit } => {
+                write!(f, "Scan exceeded the limit of {} directories", limit)
+            }
+            KushnError::FileTooLarge { path, size, limit } => write!(
+                f,
+                "{} is {} bytes, exceeding the limit of {} bytes",
+                path.display(),
+                size,
+                limit
+            ),
+            KushnError::InvalidSize(value) => write!(f, "Invalid size: {}", value),
+            KushnError::InvalidSumsLine { line } => {
+                write!(f, "Malformed checksum line {}", line)
+            }
+            KushnError::SymlinkLoop { path } => write!(
+                f,
+                "File system loop found: {} points to an ancestor",
+                path.display()
+            ),
+            KushnError::InvalidManifest(path) if path.is_empty() => {
+                write!(f, "Malformed manifest")
+            }
+            KushnError::InvalidManifest(path) => {
+                write!(f, "Malformed manifest entry at {}", path)
+            }
+            KushnError::DuplicatePath(path) => write!(f, "Duplicate manifest path {}", path),
+            KushnError::UnknownAlgorithm(name) => write!(f, "Unknown hash algorithm {}", name),
+            KushnError::InvalidBloomFilter(reason) => write!(f, "Invalid bloom filter: {}", reason),
+            KushnError::IgnoreIncludeCycle { path } => {
+                write!(f, "Ignore file {} includes itself", path.display())
+            }
+            KushnError::OutsideBaseDir { path, base } => write!(
+                f,
+                "{} is not inside the base directory {}",
+                path.display(),
+                base.display()
+            ),
+            #[cfg(feature = "http")]
+            KushnError::Http(err) => write!(f, "Failed to fetch manifest: {}", err),
+            KushnError::NoFilesFound { path } => {
+                write!(f, "No files found to hash in {}", path.display())
+            }
+            KushnError::TooFewComponents { path, count } => {
+                write!(f, "{} has too few components to strip {}", path, count)
+            }
+            KushnError::InvalidArgument(message) => write!(f, "{}", message),
+            KushnError::File { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl Error for KushnError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            KushnError::Io(err) => Some(err),
+            KushnError::Walk(err) => Some(err),
+            KushnError::Pattern(err) => Some(err),
+            KushnError::Json(err) => Some(err),
+            #[cfg(feature = "http")]
+            KushnError::Http(err) => Some(err),
+            KushnError::File { source, .. } => Some(source.as_ref()),
+            KushnError::FileLimitExceeded { .. }
+            | KushnError::DirLimitExceeded { .. }
+            | KushnError::FileTooLarge { .. }
+            | KushnError::InvalidSize(_)
+            | KushnError::InvalidSumsLine { .. }
+            | KushnError::SymlinkLoop { .. }
+            | KushnError::InvalidManifest(_)
+            | KushnError::DuplicatePath(_)
+            | KushnError::UnknownAlgorithm(_)
+            | KushnError::InvalidBloomFilter(_)
+            | KushnError::IgnoreIncludeCycle { .. }
+            | KushnError::OutsideBaseDir { .. }
+            | KushnError::NoFilesFound { .. }
+            | KushnError::TooFewComponents { .. }
+            | KushnError::InvalidArgument(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for KushnError {
+    fn from(err: io::Error) -> Self {
+        KushnError::Io(err)
+    }
+}
+
+impl From<walkdir::Error> for KushnError {
+    fn from(err: walkdir::Error) -> Self {
+        KushnError::Walk(err)
+    }
+}
+
+impl From<glob::PatternError> for KushnError {
+    fn from(err: glob::PatternError) -> Self {
+        KushnError::Pattern(err)
+    }
+}
+
+impl From<serde_json::Error> for KushnError {
+    fn from(err: serde_json::Error) -> Self {
+        KushnError::Json(err)
+    }
+}
+
+#[cfg(feature = "http")]
+impl From<reqwest::Error> for KushnError {
+    fn from(err: reqwest::Error) -> Self {
+        KushnError::Http(err)
+    }
+}
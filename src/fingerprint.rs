@@ -0,0 +1,41 @@
+use sha2::{Digest, Sha256};
+
+use crate::hash::decode_hex;
+
+const ADJECTIVES: [&str; 64] = [
+    "able", "amber", "ancient", "bold", "brave", "bright", "brisk", "calm", "clever", "cosmic",
+    "crisp", "curious", "daring", "deep", "eager", "early", "electric", "fancy", "fierce",
+    "frosty", "gentle", "giant", "golden", "grand", "happy", "hidden", "honest", "humble", "icy",
+    "jolly", "keen", "kind", "lively", "lucky", "mellow", "mighty", "misty", "noble", "odd",
+    "patient", "plain", "polite", "proud", "quick", "quiet", "rapid", "rare", "royal", "rusty",
+    "shiny", "silent", "silver", "sleek", "smooth", "solid", "steady", "sunny", "swift", "tidy",
+    "vivid", "warm", "wild", "wise", "young",
+];
+
+const NOUNS: [&str; 64] = [
+    "anchor", "badger", "beacon", "bison", "canyon", "cedar", "comet", "condor", "coral", "crane",
+    "delta", "dune", "eagle", "ember", "falcon", "fern", "fjord", "fox", "galaxy", "glacier",
+    "harbor", "hawk", "heron", "island", "jaguar", "kestrel", "lagoon", "lantern", "lynx", "maple",
+    "meadow", "meteor", "moose", "nebula", "oasis", "orca", "otter", "owl", "panda", "pebble",
+    "pine", "planet", "prairie", "quartz", "raven", "reef", "river", "robin", "saber", "salmon",
+    "seal", "sparrow", "summit", "thunder", "tiger", "tundra", "valley", "viper", "walrus",
+    "willow", "wolf", "yak", "zebra", "zephyr",
+];
+
+fn digest_bytes(root_hash: &str) -> Vec<u8> {
+    match decode_hex(root_hash) {
+        Some(bytes) if bytes.len() >= 4 => bytes,
+        _ => Sha256::digest(root_hash.as_bytes()).to_vec(),
+    }
+}
+
+/// Maps a digest to an `adjective-noun-NNNN` phrase for reading out loud or
+/// scanning logs. The phrase carries only ~26 bits of the digest, so it identifies
+/// a tree for humans but must not replace comparing the full hash.
+pub fn memorable_fingerprint(root_hash: &str) -> String {
+    let bytes = digest_bytes(root_hash);
+    let adjective = ADJECTIVES[bytes[0] as usize % ADJECTIVES.len()];
+    let noun = NOUNS[bytes[1] as usize % NOUNS.len()];
+    let number = u16::from_be_bytes([bytes[2], bytes[3]]) % 10_000;
+    format!("{}-{}-{:04}", adjective, noun, number)
+}
@@ -0,0 +1,422 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::hash::Hasher as _;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use md5::Md5;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use twox_hash::XxHash64;
+
+use crate::paths::open_file;
+use crate::KushnError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+    /// Broken for collision resistance; only for matching existing checksums.
+    Sha1,
+    /// Broken for collision resistance; only for matching existing checksums.
+    Md5,
+    Blake3,
+    /// Not cryptographic; for change detection and deduplication only.
+    Crc32,
+    /// Not cryptographic; for change detection and deduplication only.
+    XxHash64,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 7] = [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha512,
+        HashAlgorithm::Sha1,
+        HashAlgorithm::Md5,
+        HashAlgorithm::Blake3,
+        HashAlgorithm::Crc32,
+        HashAlgorithm::XxHash64,
+    ];
+
+    /// Lowercase name, as accepted by `--algorithm` and written to manifests.
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Crc32 => "crc32",
+            HashAlgorithm::XxHash64 => "xxhash64",
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = KushnError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        HashAlgorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| KushnError::UnknownAlgorithm(name.to_owned()))
+    }
+}
+
+/// How digests are written. Digests are computed as lowercase hex and
+/// converted, so every encoding carries the same bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Encoding {
+    #[default]
+    HexLower,
+    HexUpper,
+    /// Standard alphabet with padding, a third shorter than hex.
+    Base64,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 3] = [Encoding::HexLower, Encoding::HexUpper, Encoding::Base64];
+
+    /// Name accepted by `--encoding`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::HexLower => "hex",
+            Encoding::HexUpper => "HEX",
+            Encoding::Base64 => "base64",
+        }
+    }
+
+    pub fn encode(self, digest: &[u8]) -> String {
+        match self {
+            Encoding::HexLower => encode_hex(digest),
+            Encoding::HexUpper => encode_hex(digest).to_ascii_uppercase(),
+            Encoding::Base64 => BASE64.encode(digest),
+        }
+    }
+
+    /// Re-encodes a lowercase hex digest, as returned by the `calculate_*`
+    /// functions. Anything that is not hex is returned as it is.
+    pub fn reencode(self, hex: String) -> String {
+        match (self, decode_hex(&hex)) {
+            (Encoding::HexLower, _) | (_, None) => hex,
+            (Encoding::HexUpper, _) => hex.to_ascii_uppercase(),
+            (Encoding::Base64, Some(digest)) => BASE64.encode(digest),
+        }
+    }
+
+    /// Lowercase hex of a digest written in this encoding, the inverse of
+    /// [`reencode`](Self::reencode).
+    pub(crate) fn to_hex(self, digest: &str) -> Option<String> {
+        match self {
+            Encoding::HexLower => Some(digest.to_owned()),
+            Encoding::HexUpper => Some(digest.to_ascii_lowercase()),
+            Encoding::Base64 => BASE64.decode(digest).ok().map(|digest| encode_hex(&digest)),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = KushnError;
+
+    /// Names are case-sensitive so that `hex` and `HEX` can be told apart;
+    /// `hex-upper` is accepted for the latter as well.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "hex-upper" => Ok(Encoding::HexUpper),
+            _ => Encoding::ALL
+                .into_iter()
+                .find(|encoding| encoding.name() == name)
+                .ok_or_else(|| {
+                    KushnError::InvalidArgument(format!(
+                        "Unknown encoding {}. Expected hex, HEX or base64.",
+                        name
+                    ))
+                }),
+        }
+    }
+}
+
+pub(crate) enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Sha1(Sha1),
+    Md5(Md5),
+    Blake3(Box<blake3::Hasher>),
+    Crc32(crc32fast::Hasher),
+    XxHash64(XxHash64),
+}
+
+impl Hasher {
+    pub(crate) fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            HashAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+            HashAlgorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+            HashAlgorithm::XxHash64 => Hasher::XxHash64(XxHash64::with_seed(0)),
+        }
+    }
+
+    /// Starts a hasher, prefixed with `bound_path || 0x00` when given; see
+    /// [`calculate_path_bound_hash`].
+    fn bound(algorithm: HashAlgorithm, bound_path: Option<&str>) -> Self {
+        let mut hasher = Hasher::new(algorithm);
+        if let Some(bound_path) = bound_path {
+            hasher.update(bound_path.as_bytes());
+            hasher.update(&[0u8]);
+        }
+        hasher
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            Hasher::Crc32(hasher) => hasher.update(data),
+            Hasher::XxHash64(hasher) => hasher.write(data),
+        }
+    }
+
+    pub(crate) fn finalize_hex(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Sha512(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Sha1(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Md5(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Hasher::Crc32(hasher) => format!("{:08x}", hasher.finalize()),
+            Hasher::XxHash64(hasher) => format!("{:016x}", hasher.finish()),
+        }
+    }
+}
+
+pub fn calculate_file_hash<P: AsRef<Path>>(file_path: P) -> Result<String, io::Error> {
+    calculate_file_hash_with(file_path, HashAlgorithm::Sha256)
+}
+
+pub fn calculate_file_hash_with<P: AsRef<Path>>(
+    file_path: P,
+    algorithm: HashAlgorithm,
+) -> Result<String, io::Error> {
+    calculate_hash_reader(open_file(file_path)?, algorithm)
+}
+
+/// Same as [`calculate_file_hash_with`], with the digest written in `encoding`.
+pub fn calculate_file_hash_encoded<P: AsRef<Path>>(
+    file_path: P,
+    algorithm: HashAlgorithm,
+    encoding: Encoding,
+) -> Result<String, io::Error> {
+    calculate_file_hash_with(file_path, algorithm).map(|hash| encoding.reencode(hash))
+}
+
+/// Digest of everything `reader` yields, e.g. standard input or an in-memory
+/// buffer, read the same way as files are.
+pub fn calculate_hash_reader<R: Read>(
+    mut reader: R,
+    algorithm: HashAlgorithm,
+) -> Result<String, io::Error> {
+    hash_reader_inspecting(&mut reader, algorithm, None, |_| {})
+}
+
+/// Read size used by [`calculate_file_hash`] and directory scans. Large enough
+/// to keep the number of read calls on multi-gigabyte files low.
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// SHA-256 of a file read through a buffer of `buffer_size` bytes, for tuning
+/// the read size to the storage, e.g. larger reads on network filesystems.
+pub fn calculate_file_hash_buffered<P: AsRef<Path>>(
+    file_path: P,
+    buffer_size: usize,
+) -> Result<String, io::Error> {
+    let mut file = open_file(file_path)?;
+    let mut buffer = vec![0u8; buffer_size.max(1)];
+    hash_reader_buffered(&mut file, HashAlgorithm::Sha256, None, &mut buffer, |_| {})
+}
+
+/// SHA-256 of a file hashed straight from a memory map, which saves copying
+/// very large files through a read buffer. Empty files, which some platforms
+/// refuse to map, and files that cannot be mapped are read normally instead.
+pub fn calculate_file_hash_mmap<P: AsRef<Path>>(file_path: P) -> Result<String, io::Error> {
+    let mut file = open_file(file_path)?;
+    hash_file_mapped(&mut file, HashAlgorithm::Sha256, None, |_| {})
+}
+
+pub(crate) fn hash_file_mapped<F>(
+    file: &mut File,
+    algorithm: HashAlgorithm,
+    bound_path: Option<&str>,
+    mut inspect: F,
+) -> Result<String, io::Error>
+where
+    F: FnMut(&[u8]),
+{
+    if file.metadata()?.len() > 0 {
+        // SAFETY: the map is only read while this function runs. A file truncated
+        // by another process meanwhile faults on access, which plain reads would
+        // not; callers opt into mapping knowing that.
+        if let Ok(map) = unsafe { Mmap::map(&*file) } {
+            let mut hasher = Hasher::bound(algorithm, bound_path);
+            hasher.update(&map);
+            inspect(&map);
+            return Ok(hasher.finalize_hex());
+        }
+    }
+    hash_reader_inspecting(file, algorithm, bound_path, inspect)
+}
+
+/// Hashes `path || 0x00 || content`, binding the digest to the file's location:
+/// identical content at two paths produces two different digests, unlike
+/// [`calculate_file_hash`]. Paths never contain a NUL byte, so the delimiter keeps
+/// the boundary between path and content unambiguous.
+pub fn calculate_path_bound_hash<P: AsRef<Path>>(
+    file_path: P,
+    bound_path: &str,
+) -> Result<String, io::Error> {
+    hash_file_inspecting(file_path, HashAlgorithm::Sha256, Some(bound_path), |_| {})
+}
+
+/// Hashes a file while handing every chunk read to `tap` as well, so auxiliary
+/// properties (line counts, entropy, ...) can be computed in the same pass.
+pub fn calculate_file_hash_tapped<P, F>(
+    file_path: P,
+    algorithm: HashAlgorithm,
+    tap: F,
+) -> Result<String, io::Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(&[u8]),
+{
+    hash_file_inspecting(file_path, algorithm, None, tap)
+}
+
+pub(crate) fn hash_file_inspecting<P, F>(
+    file_path: P,
+    algorithm: HashAlgorithm,
+    bound_path: Option<&str>,
+    inspect: F,
+) -> Result<String, io::Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(&[u8]),
+{
+    let mut file = open_file(file_path)?;
+    hash_reader_inspecting(&mut file, algorithm, bound_path, inspect)
+}
+
+thread_local! {
+    // Reused across files so that scans of many small files do not allocate a
+    // fresh read buffer for each one.
+    static READ_BUFFER: RefCell<Vec<u8>> = RefCell::new(vec![0u8; DEFAULT_BUFFER_SIZE]);
+}
+
+pub(crate) fn hash_reader_inspecting<R, F>(
+    reader: &mut R,
+    algorithm: HashAlgorithm,
+    bound_path: Option<&str>,
+    inspect: F,
+) -> Result<String, io::Error>
+where
+    R: Read,
+    F: FnMut(&[u8]),
+{
+    READ_BUFFER.with(|buffer| match buffer.try_borrow_mut() {
+        Ok(mut buffer) => hash_reader_buffered(reader, algorithm, bound_path, &mut buffer, inspect),
+        // Only when `inspect` itself hashes another file on this thread.
+        Err(_) => {
+            let mut buffer = vec![0u8; DEFAULT_BUFFER_SIZE];
+            hash_reader_buffered(reader, algorithm, bound_path, &mut buffer, inspect)
+        }
+    })
+}
+
+fn hash_reader_buffered<R, F>(
+    reader: &mut R,
+    algorithm: HashAlgorithm,
+    bound_path: Option<&str>,
+    buffer: &mut [u8],
+    mut inspect: F,
+) -> Result<String, io::Error>
+where
+    R: Read,
+    F: FnMut(&[u8]),
+{
+    let mut hasher = Hasher::bound(algorithm, bound_path);
+    loop {
+        let read = match reader.read(buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        hasher.update(&buffer[..read]);
+        inspect(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize_hex())
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct UniformByteCheck {
+    byte: Option<u8>,
+    mixed: bool,
+}
+
+impl UniformByteCheck {
+    pub(crate) fn update(&mut self, chunk: &[u8]) {
+        if self.mixed || chunk.is_empty() {
+            return;
+        }
+        let byte = *self.byte.get_or_insert(chunk[0]);
+        self.mixed = chunk.iter().any(|&b| b != byte);
+    }
+
+    pub(crate) fn is_uniform(&self) -> bool {
+        self.byte.is_some() && !self.mixed
+    }
+}
+
+pub(crate) fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| {
+            value
+                .get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+        })
+        .collect()
+}
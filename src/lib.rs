@@ -0,0 +1,131 @@
+mod archive;
+#[cfg(feature = "async")]
+mod asynchronous;
+mod backend;
+mod bloom;
+mod cache;
+mod chunk;
+mod content;
+mod diff;
+mod duplicates;
+mod error;
+mod fingerprint;
+mod hash;
+mod manifest;
+mod merkle;
+mod options;
+mod parallel;
+mod paths;
+mod patterns;
+mod scan;
+mod size;
+mod timestamp;
+mod verify;
+mod walk;
+mod warning;
+
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+pub use archive::process_tar;
+#[cfg(feature = "async")]
+pub use asynchronous::{calculate_file_hash_async, process_directory_async};
+pub use backend::{hash_backend, hash_cpu_features};
+pub use bloom::{bloom_contains, build_hash_bloom, BloomFilter};
+pub use cache::{CacheStore, DEFAULT_CACHE_FILE};
+pub use chunk::{chunk_file_cdc, MIN_AVG_CHUNK_SIZE};
+pub use content::DEFAULT_CONTENT_TYPE;
+pub use diff::{
+    compare_directories, content_difference, diff_manifests, symmetric_difference, DirectoryDiff,
+};
+pub use duplicates::{find_copies_of, find_duplicates};
+pub use error::{KushnError, KushnResult};
+pub use fingerprint::memorable_fingerprint;
+pub use hash::{
+    calculate_file_hash, calculate_file_hash_buffered, calculate_file_hash_encoded,
+    calculate_file_hash_mmap, calculate_file_hash_tapped, calculate_file_hash_with,
+    calculate_hash_reader, calculate_path_bound_hash, Encoding, HashAlgorithm, DEFAULT_BUFFER_SIZE,
+};
+pub use manifest::{
+    build_manifest, directory_root_hash, estimate_manifest_bytes, from_nested_json, load_manifest,
+    make_reproducible, parse_coreutils_sums, parse_manifest, serialize_entries, sort_entries,
+    strip_components, summarize_manifest, to_cyclonedx, to_nested_json, to_ordered_map,
+    to_stable_json, to_tree_string, write_json_entries, Manifest, ManifestSummary, OutputFormat,
+    CYCLONEDX_SPEC_VERSION, MANIFEST_SCHEMA, MANIFEST_SCHEMA_VERSION,
+};
+pub use merkle::{merkle_proof, merkle_root, verify_merkle_proof, MerkleTree, Side};
+pub use options::{
+    BaseStripping, ProcessOptions, SymlinkPolicy, HARDENED_MAX_FILES, MMAP_MIN_SIZE,
+};
+pub use parallel::{hash_files_parallel, process_directory_queued, scan_directory_queued};
+pub use paths::{normalize_path, to_extended_length_path, WINDOWS_MAX_PATH};
+pub use patterns::{
+    build_dir_ignore_patterns, build_file_ignore_patterns, load_ignore_file, normalize_pattern,
+    parse_ignore_file, COMMENT_PREFIX, IGNORE_FILE_NAME, INCLUDE_DIRECTIVE, NEGATION_PREFIX,
+};
+pub use scan::{
+    count_files, list_files, process_directory, process_directory_cancellable,
+    process_directory_filtered, process_directory_incremental, process_directory_iter,
+    process_directory_timed, process_directory_with, process_directory_with_errors,
+    process_directory_with_progress, process_file, process_file_with, process_reader,
+    scan_directory, scan_directory_streaming, FileError, PhaseTimings, ScanReport,
+};
+pub use size::parse_size;
+#[cfg(feature = "http")]
+pub use verify::verify_against_url;
+pub use verify::{
+    verify_against_coreutils, verify_and_update, verify_directory, verify_directory_with,
+    VerifyReport,
+};
+pub use walk::Traversal;
+pub use warning::Warning;
+
+// Fields serialize in declaration order: keep `path` and `hash` first and add
+// any optional fields after them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileHash {
+    pub path: String,
+    pub hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Owner and group ids, recorded on Unix with
+    /// [`ProcessOptions::with_ownership`]. Always `None` on other platforms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+    /// Digest that produced `hash`; `None` means SHA-256.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<HashAlgorithm>,
+    /// Size in bytes and modification time, recorded with
+    /// [`ProcessOptions::with_file_metadata`]. `modified` is written as an RFC 3339
+    /// UTC timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "timestamp")]
+    pub modified: Option<SystemTime>,
+    /// Marks an empty directory, recorded with [`ProcessOptions::with_empty_dirs`].
+    /// Its `hash` is empty.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_dir: bool,
+}
+
+impl FileHash {
+    pub fn new(path: String, hash: String) -> Self {
+        FileHash {
+            path,
+            hash,
+            link_target: None,
+            content_type: None,
+            uid: None,
+            gid: None,
+            algorithm: None,
+            size: None,
+            modified: None,
+            is_dir: false,
+        }
+    }
+}
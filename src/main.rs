@@ -1,111 +1,364 @@
 use std::env;
 use std::fs;
 use std::io::{self, Write};
-use std::path::Path;
-
-use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
-use walkdir::WalkDir;
-
-#[derive(Serialize, Deserialize)]
-struct FileHash {
-    path: String,
-    hash: String,
-}
-
-fn calculate_file_hash<P: AsRef<Path>>(file_path: P) -> Result<String, io::Error> {
-    let mut file = fs::File::open(file_path)?;
-    let mut hasher = Sha256::new();
-    io::copy(&mut file, &mut hasher)?;
-    let hash_result = hasher.finalize();
-    Ok(format!("{:x}", hash_result))
-}
-
-fn process_file<P: AsRef<Path>>(
-    file_path: P,
-    ignore: &Vec<String>,
-) -> Result<Option<FileHash>, io::Error> {
-    let file_path = file_path.as_ref();
-    let relative_path = file_path
-        .strip_prefix(env::current_dir()?)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-    let ignore_patterns = ignore
-        .iter()
-        .map(|pattern| glob::Pattern::new(&format!("**/{}", pattern)))
-        .collect::<Result<Vec<_>, _>>()
-        .unwrap();
-
-    if ignore_patterns
-        .iter()
-        .any(|pattern| pattern.matches_path_with(&relative_path, glob::MatchOptions::new()))
-    {
-        return Ok(None);
-    }
-
-    let hash = calculate_file_hash(&file_path)?;
-    let path_string = relative_path.to_string_lossy().into_owned();
-    Ok(Some(FileHash {
-        path: path_string,
-        hash,
-    }))
-}
-
-fn process_directory<P: AsRef<Path>>(directory_path: P, ignore: &Vec<String>) -> Vec<FileHash> {
-    let mut results = Vec::new();
-
-    for entry in WalkDir::new(&directory_path).follow_links(true).into_iter() {
-        if let Ok(entry) = entry {
-            let path = entry.path();
-            let relative_path = path.strip_prefix(&directory_path).unwrap_or(path);
-
-            if entry.file_type().is_dir() {
-                if ignore.iter().any(|pattern| {
-                    let full_pattern = format!("{}/**", pattern.replace("\\", "/"));
-                    let pattern = glob::Pattern::new(&full_pattern).unwrap();
-                    pattern.matches_path_with(relative_path, glob::MatchOptions::new())
-                }) {
-                    continue;
-                }
-            } else {
-                if let Some(relative_path_str) = relative_path.to_str() {
-                    if ignore.iter().any(|pattern| {
-                        let full_pattern = format!("{}/**", pattern.replace("\\", "/"));
-                        let pattern = glob::Pattern::new(&full_pattern).unwrap();
-                        pattern.matches(relative_path_str)
-                    }) {
-                        continue;
-                    }
-                }
-            }
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use kushn::{
+    calculate_file_hash_with, calculate_hash_reader, compare_directories, diff_manifests,
+    directory_root_hash, find_duplicates, hash_backend, hash_cpu_features, list_files,
+    load_ignore_file, load_manifest, make_reproducible, normalize_path, parse_size,
+    process_directory_with_progress, process_reader, scan_directory_queued,
+    scan_directory_streaming, serialize_entries, sort_entries, strip_components,
+    verify_directory_with, write_json_entries, DirectoryDiff, Encoding, FileHash, HashAlgorithm,
+    KushnError, KushnResult, OutputFormat, ProcessOptions, ScanReport, SymlinkPolicy,
+    IGNORE_FILE_NAME,
+};
+use serde::Serialize;
+use serde_json::json;
+
+fn flag_value(flag: &str) -> Option<String> {
+    let index = env::args().position(|arg| arg == flag)?;
+    env::args().nth(index + 1)
+}
+
+/// Values of a flag that may be given several times.
+fn flag_values(flag: &str) -> Vec<String> {
+    let args: Vec<String> = env::args().collect();
+    args.windows(2)
+        .filter(|pair| pair[0] == flag)
+        .map(|pair| pair[1].clone())
+        .collect()
+}
+
+fn thread_count(flag: &str) -> KushnResult<Option<usize>> {
+    flag_value(flag)
+        .map(|value| match value.parse::<usize>() {
+            Ok(threads) if threads > 0 => Ok(threads),
+            _ => Err(KushnError::InvalidArgument(format!(
+                "Invalid {} {}. Expected a number of threads.",
+                flag, value
+            ))),
+        })
+        .transpose()
+}
+
+fn run_compare(dir_a: &str, dir_b: &str, options: &ProcessOptions) -> KushnResult<()> {
+    print_diff(&compare_directories(dir_a, dir_b, options)?);
+    Ok(())
+}
+
+fn run_diff(old_file: &str, new_file: &str) -> KushnResult<()> {
+    let mut manifests = Vec::new();
+    for file in [old_file, new_file] {
+        let (entries, warnings) = load_manifest(file).map_err(|err| err.with_path(file))?;
+        for warning in &warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        manifests.push(entries);
+    }
+    print_diff(&diff_manifests(&manifests[0], &manifests[1]));
+    Ok(())
+}
+
+/// Prints the changes and exits with status 1 if there are any.
+fn print_diff(diff: &DirectoryDiff) {
+    for path in &diff.added {
+        println!("+ {}", path);
+    }
+    for path in &diff.removed {
+        println!("- {}", path);
+    }
+    for path in &diff.changed {
+        println!("~ {}", path);
+    }
+    println!(
+        "{} added, {} removed, {} changed.",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len()
+    );
+
+    if !diff.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn run_verify(manifest_file: &str, directory: &Path, options: ProcessOptions) -> KushnResult<()> {
+    let manifest_path = directory.join(manifest_file);
+    let (mut manifest, warnings) =
+        load_manifest(&manifest_path).map_err(|err| err.with_path(manifest_file))?;
+    for warning in &warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    // Manifests written by older versions list themselves, with a hash taken
+    // before that entry was added.
+    let manifest_entry = normalize_path(
+        manifest_path
+            .strip_prefix(directory)
+            .unwrap_or(&manifest_path),
+    );
+    manifest.retain(|entry| entry.path != manifest_entry);
+
+    let options = match (flag_value("--algorithm"), manifest.first()) {
+        (None, Some(entry)) => options.with_algorithm(entry.algorithm.unwrap_or_default()),
+        _ => options,
+    };
+    // Empty directories have to be looked for if the manifest records any.
+    let options = if manifest.iter().any(|entry| entry.is_dir) {
+        options.with_empty_dirs(true)
+    } else {
+        options
+    };
+    let mut report = verify_directory_with(directory, &manifest, &options)?;
+    report.added.retain(|path| *path != manifest_entry);
+
+    for path in &report.added {
+        println!("+ {}", path);
+    }
+    for path in &report.missing {
+        println!("- {}", path);
+    }
+    for path in &report.changed {
+        println!("~ {}", path);
+    }
+    println!(
+        "{} matched, {} modified, {} missing, {} added.",
+        report.matched.len(),
+        report.changed.len(),
+        report.missing.len(),
+        report.added.len()
+    );
+
+    if !report.is_clean() {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+// Conventional exit status for a process stopped by SIGINT.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+// Names the ignore files to read instead of `.kushnignore`.
+const IGNORE_FILE_ENV: &str = "KUSHN_IGNORE_FILE";
+
+const DEFAULT_SHORT_LENGTH: usize = 12;
 
-            if let Ok(Some(file_hash)) = process_file(path, ignore) {
-                results.push(file_hash);
+const SSE_PROGRESS_INTERVAL: usize = 100;
+
+fn send_event<T: Serialize>(event: &str, data: &T) -> KushnResult<()> {
+    let data = serde_json::to_string(data)?;
+    let mut stdout = io::stdout().lock();
+    write!(stdout, "event: {}\ndata: {}\n\n", event, data)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn run_sse(directory: &Path, options: &ProcessOptions) -> KushnResult<()> {
+    let mut hashed = 0;
+    // The callback cannot fail the scan, so the first write error is kept for
+    // after it, and later events are dropped.
+    let mut send_result = Ok(());
+    let result = scan_directory_streaming(directory, options, |entry| {
+        hashed += 1;
+        if send_result.is_ok() {
+            send_result = send_event("file", entry);
+        }
+        if send_result.is_ok() && hashed % SSE_PROGRESS_INTERVAL == 0 {
+            send_result = send_event("progress", &json!({ "hashed": hashed }));
+        }
+    });
+    send_result?;
+
+    match result {
+        Ok(report) => {
+            for warning in &report.warnings {
+                send_event("warning", &json!({ "message": warning.to_string() }))?;
             }
-        } else if let Err(err) = entry {
-            eprintln!("Error processing entry: {:?}", err);
+            for (path, err) in &report.errors {
+                send_event(
+                    "error",
+                    &json!({ "path": path.display().to_string(), "message": err.to_string() }),
+                )?;
+            }
+            send_event(
+                "done",
+                &json!({
+                    "hashed": report.entries.len(),
+                    "warnings": report.warnings.len(),
+                    "errors": report.errors.len(),
+                    "root_hash": directory_root_hash(&report.entries),
+                }),
+            )
+        }
+        Err(err) => {
+            send_event("error", &json!({ "message": err.to_string() }))?;
+            Err(err)
         }
     }
+}
 
-    results
+fn print_hash_info(algorithm: HashAlgorithm) {
+    println!("Algorithm: {}", algorithm);
+    println!("Backend: {}", hash_backend(algorithm));
+    let features = hash_cpu_features();
+    if features.is_empty() {
+        println!("CPU features: none checked on this architecture");
+        return;
+    }
+    println!("CPU features:");
+    for (name, detected) in features {
+        println!("  {}: {}", name, if detected { "yes" } else { "no" });
+    }
 }
 
 fn main() {
-    let current_dir = env::current_dir().expect("Failed to get current directory.");
-    let ignore_file_path = current_dir.join(".kushnignore");
-
-    let ignore_patterns: Vec<String> = if ignore_file_path.exists() {
-        let ignore_contents =
-            fs::read_to_string(ignore_file_path).expect("Failed to read .kushnignore file.");
-        ignore_contents
-            .lines()
-            .map(|s| s.trim().to_string())
-            .collect()
+    if let Err(err) = run() {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run() -> KushnResult<()> {
+    let symlinks = if env::args().any(|arg| arg == "--follow-symlinks") {
+        SymlinkPolicy::Follow
+    } else if env::args().any(|arg| arg == "--hash-symlinks") {
+        SymlinkPolicy::HashLinkTarget
+    } else {
+        SymlinkPolicy::Skip
+    };
+    let algorithm = match flag_value("--algorithm") {
+        Some(name) => name.parse()?,
+        None => HashAlgorithm::Sha256,
+    };
+    let encoding = match flag_value("--encoding") {
+        Some(name) => name.parse()?,
+        None => Encoding::HexLower,
+    };
+    // `--short` takes an optional length.
+    let truncate = env::args().any(|arg| arg == "--short").then(|| {
+        flag_value("--short")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_SHORT_LENGTH)
+    });
+
+    if env::args().any(|arg| arg == "--hash-info") {
+        print_hash_info(algorithm);
+        return Ok(());
+    }
+
+    if env::args().nth(1).as_deref() == Some("compare") {
+        let options = ProcessOptions::new()
+            .with_symlinks(symlinks)
+            .with_algorithm(algorithm)
+            .with_encoding(encoding)
+            .with_truncate(truncate);
+        return match (env::args().nth(2), env::args().nth(3)) {
+            (Some(dir_a), Some(dir_b)) => run_compare(&dir_a, &dir_b, &options),
+            _ => Err(KushnError::InvalidArgument(
+                "Usage: kushn compare <dir_a> <dir_b>".to_owned(),
+            )),
+        };
+    }
+
+    if let Some(index) = env::args().position(|arg| arg == "--diff") {
+        return match (env::args().nth(index + 1), env::args().nth(index + 2)) {
+            (Some(old_file), Some(new_file)) => run_diff(&old_file, &new_file),
+            _ => Err(KushnError::InvalidArgument(
+                "Usage: kushn --diff <old.json> <new.json>".to_owned(),
+            )),
+        };
+    }
+
+    if env::args().any(|arg| arg == "--stdin") {
+        let hash = calculate_hash_reader(io::stdin().lock(), algorithm)
+            .map_err(|err| KushnError::from(err).with_path("<stdin>"))?;
+        let mut hash = encoding.reencode(hash);
+        if let Some(length) = truncate {
+            hash.truncate(length);
+        }
+        println!("{}", hash);
+        return Ok(());
+    }
+
+    let format = match flag_value("--format").as_deref() {
+        None if env::args().any(|arg| arg == "--with-header") => OutputFormat::Wrapped,
+        None | Some("json") => OutputFormat::Json,
+        Some("nested") => OutputFormat::Nested,
+        Some("wrapped") => OutputFormat::Wrapped,
+        Some("csv") => OutputFormat::Csv,
+        Some("sfv") => OutputFormat::Sfv,
+        Some(other) => {
+            return Err(KushnError::InvalidArgument(format!(
+                "Unknown output format {}. Expected json, nested, wrapped, csv or sfv.",
+                other
+            )));
+        }
+    };
+
+    if let Some(logical_path) = flag_value("--stdin-as") {
+        let entry = process_reader(&mut io::stdin().lock(), &logical_path, algorithm)
+            .map_err(|err| err.with_path("<stdin>"))?;
+        let output = serialize_entries(&[entry], format)?;
+        if format.is_json() {
+            println!("{}", output);
+        } else {
+            print!("{}", output);
+        }
+        return Ok(());
+    }
+
+    let current_dir = env::current_dir()?;
+    let ignore_file_name = env::var(IGNORE_FILE_ENV)
+        .ok()
+        .filter(|name| !name.is_empty());
+    // A file asked for by flag or environment must exist; only the default
+    // one is optional.
+    let (ignore_file_path, required) = match (flag_value("--ignore-file"), &ignore_file_name) {
+        (Some(path), _) => (current_dir.join(path), true),
+        (None, Some(name)) => (current_dir.join(name), true),
+        (None, None) => (current_dir.join(IGNORE_FILE_NAME), false),
+    };
+
+    let ignore_patterns: Vec<String> = if required || ignore_file_path.exists() {
+        load_ignore_file(&ignore_file_path).map_err(|err| err.with_path(&ignore_file_path))?
     } else {
         Vec::new()
     };
 
-    let mut file_hashes = process_directory(&current_dir, &ignore_patterns);
+    let max_file_size = flag_value("--max-file-size")
+        .map(|value| parse_size(&value))
+        .transpose()?;
+    let min_size = flag_value("--min-size")
+        .map(|value| parse_size(&value))
+        .transpose()?;
+    let max_size = flag_value("--max-size")
+        .map(|value| parse_size(&value))
+        .transpose()?;
+
+    let max_depth = flag_value("--max-depth")
+        .map(|value| {
+            value.parse::<usize>().map_err(|_| {
+                KushnError::InvalidArgument(format!(
+                    "Invalid --max-depth {}. Expected a number of levels.",
+                    value
+                ))
+            })
+        })
+        .transpose()?;
+
+    let strip_count = flag_value("--strip-components")
+        .map(|value| {
+            value.parse::<usize>().map_err(|_| {
+                KushnError::InvalidArgument(format!(
+                    "Invalid --strip-components {}. Expected a number of components.",
+                    value
+                ))
+            })
+        })
+        .transpose()?;
 
     let output_file_name = match env::args().position(|arg| arg == "--name") {
         Some(index) => {
@@ -120,31 +373,233 @@ fn main() {
         }
         None => "kushn_result.json".to_owned(),
     };
+    // Each `--output` gets the format of its extension, writing all of them
+    // from one scan; `.json` files take the JSON layout asked for with `--format`.
+    let output_names = flag_values("--output");
+    let outputs = if output_names.is_empty() {
+        vec![(output_file_name, format)]
+    } else if env::args().any(|arg| arg == "--name") {
+        return Err(KushnError::InvalidArgument(
+            "--name cannot be combined with --output. Pass that file as another --output."
+                .to_owned(),
+        ));
+    } else {
+        output_names
+            .into_iter()
+            .map(|name| match OutputFormat::from_extension(&name) {
+                Some(OutputFormat::Json) if format.is_json() => Ok((name, format)),
+                Some(inferred) => Ok((name, inferred)),
+                None => Err(KushnError::InvalidArgument(format!(
+                    "Cannot tell the format of --output {} from its extension. Expected .json, .csv or .sfv.",
+                    name
+                ))),
+            })
+            .collect::<KushnResult<Vec<_>>>()?
+    };
 
-    let output_file_path = current_dir.join(&output_file_name);
-    let output_file = fs::File::create(&output_file_path).expect("Failed to create output file.");
+    // The previous manifest is not part of the tree it describes. Modes that
+    // write none keep the files of those names; `--verify` leaves out the
+    // manifest it checks by itself.
+    let writes_manifest = ![
+        "--sse",
+        "--verify",
+        "--list",
+        "--duplicates",
+        "--print-root-hash",
+    ]
+    .iter()
+    .any(|flag| env::args().any(|arg| arg == *flag));
+    let excluded_files = if writes_manifest {
+        outputs
+            .iter()
+            .map(|(name, _)| PathBuf::from(name))
+            .collect()
+    } else {
+        Vec::new()
+    };
 
-    let json_output =
-        serde_json::to_string_pretty(&file_hashes).expect("Failed to convert file hashes to JSON.");
+    let io_threads = thread_count("--threads-io")?;
+    let hash_threads = thread_count("--threads-hash")?;
 
-    io::BufWriter::new(&output_file)
-        .write_all(json_output.as_bytes())
-        .expect("Failed to write JSON output to file.");
+    let options = ProcessOptions::new()
+        .with_ignore(ignore_patterns)
+        .with_excluded_files(excluded_files)
+        .with_nested_ignore(true)
+        .with_ignore_file_name(ignore_file_name.unwrap_or_else(|| IGNORE_FILE_NAME.to_owned()))
+        .with_include(flag_values("--include"))
+        .with_case_insensitive(env::args().any(|arg| arg == "--ignore-case"))
+        .with_case_insensitive_paths(env::args().any(|arg| arg == "--case-insensitive-paths"))
+        .with_symlinks(symlinks)
+        .with_continue_on_error(true)
+        .with_allow_empty(env::args().any(|arg| arg == "--allow-empty"))
+        .with_algorithm(algorithm)
+        .with_encoding(encoding)
+        .with_truncate(truncate)
+        .with_mmap(env::args().any(|arg| arg == "--mmap"))
+        .with_file_metadata(env::args().any(|arg| arg == "--file-metadata"))
+        .with_max_file_size(max_file_size)
+        .with_min_size(min_size)
+        .with_empty_dirs(env::args().any(|arg| arg == "--empty-dirs"))
+        .with_max_size(max_size)
+        .with_max_depth(max_depth)
+        .with_count_first(env::args().any(|arg| arg == "--progress-total"))
+        .with_io_threads(io_threads)
+        .with_hash_threads(hash_threads);
+    let options = match flag_value("--base-dir") {
+        Some(base_dir) => options.with_base_dir(current_dir.join(base_dir)),
+        None => options,
+    };
+    if env::args().any(|arg| arg == "--sse") {
+        return run_sse(&current_dir, &options);
+    }
+    if let Some(manifest_file) = flag_value("--verify") {
+        return run_verify(&manifest_file, &current_dir, options);
+    }
+    if env::args().any(|arg| arg == "--list") {
+        for path in list_files(&current_dir, &options)? {
+            println!("{}", path);
+        }
+        return Ok(());
+    }
 
-    let result_file_hash =
-        calculate_file_hash(&output_file_path).expect("Failed to calculate file hash.");
-    let result_file_entry = FileHash {
-        path: output_file_name.clone(),
-        hash: result_file_hash,
+    let (mut file_hashes, interrupted) = if io_threads.is_some() || hash_threads.is_some() {
+        let report = scan_directory_queued(&current_dir, &options)?;
+        print_problems(&report);
+        (report.entries, false)
+    } else {
+        run_scan(&current_dir, &options)?
     };
-    file_hashes.push(result_file_entry);
+    if interrupted {
+        eprintln!(
+            "Scan interrupted after {} files; the manifest only covers those.",
+            file_hashes.len()
+        );
+    }
+    if let Some(count) = strip_count {
+        let strict = env::args().any(|arg| arg == "--strip-strict");
+        strip_components(&mut file_hashes, count, strict)?;
+        sort_entries(&mut file_hashes);
+    }
 
-    let output_file = fs::File::create(&output_file_path).expect("Failed to create output file.");
-    let json_output =
-        serde_json::to_string_pretty(&file_hashes).expect("Failed to convert file hashes to JSON.");
-    io::BufWriter::new(output_file)
-        .write_all(json_output.as_bytes())
-        .expect("Failed to write JSON output to file.");
+    if env::args().any(|arg| arg == "--duplicates") {
+        for (index, group) in find_duplicates(&file_hashes).iter().enumerate() {
+            if index > 0 {
+                println!();
+            }
+            for path in group {
+                println!("{}", path);
+            }
+        }
+        return Ok(());
+    }
+
+    if env::args().any(|arg| arg == "--print-root-hash") {
+        println!("{}", directory_root_hash(&file_hashes));
+        return Ok(());
+    }
+
+    let reproducible = env::args().any(|arg| arg == "--reproducible");
+    if reproducible {
+        make_reproducible(&mut file_hashes, symlinks);
+    }
+
+    for (output_file_name, format) in &outputs {
+        let output_file_path = current_dir.join(output_file_name);
+        let manifest_hash = write_manifest(&output_file_path, &file_hashes, *format, algorithm)
+            .map_err(|err| err.with_path(&output_file_path))?;
+        if interrupted {
+            println!(
+                "Partial file hashes (scan interrupted) saved to {}.",
+                output_file_name
+            );
+        } else {
+            println!("File hashes generated and saved to {}.", output_file_name);
+        }
+        println!("Manifest hash: {}", manifest_hash);
+    }
+    let root_hash = env::args()
+        .any(|arg| arg == "--root-hash")
+        .then(|| directory_root_hash(&file_hashes));
+    if let Some(root_hash) = root_hash {
+        println!("Root hash: {}", root_hash);
+    }
+    if interrupted {
+        process::exit(INTERRUPTED_EXIT_CODE);
+    }
+    Ok(())
+}
+
+/// Scans with progress display and Ctrl-C handling. Returns the entries and
+/// whether the scan was interrupted.
+fn run_scan(current_dir: &Path, options: &ProcessOptions) -> KushnResult<(Vec<FileHash>, bool)> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    let handler_cancel = Arc::clone(&cancel);
+    ctrlc::set_handler(move || {
+        // A second Ctrl-C quits without waiting for the partial manifest.
+        if handler_cancel.swap(true, Ordering::Relaxed) {
+            process::exit(INTERRUPTED_EXIT_CODE);
+        }
+    })
+    .map_err(io::Error::other)?;
 
-    println!("File hashes generated and saved to {}.", output_file_name);
+    let show_progress = env::args().any(|arg| arg == "--progress" || arg == "--progress-total");
+    let progress = |_: &Path, processed: usize, total: usize| {
+        if !show_progress {
+            return;
+        }
+        if total > 0 {
+            eprint!("\rHashed {}/{} files", processed, total);
+        } else {
+            eprint!("\rHashed {} files", processed);
+        }
+    };
+    let result = process_directory_with_progress(current_dir, options, Some(&cancel), progress);
+    if show_progress {
+        eprintln!();
+    }
+    let report = result?;
+    print_problems(&report);
+    Ok((report.entries, report.interrupted))
+}
+
+fn print_problems(report: &ScanReport) {
+    for warning in &report.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    for (path, err) in &report.errors {
+        eprintln!("Error processing entry {}: {}", path.display(), err);
+    }
+}
+
+/// Writes the manifest and returns its hash. Plain JSON is streamed to the file
+/// entry by entry instead of being built as a string first, and hashed by
+/// reading it back. The entries themselves are still collected, since they are
+/// sorted and shared by every output, so this only saves the serialized copy.
+fn write_manifest(
+    path: &Path,
+    entries: &[FileHash],
+    format: OutputFormat,
+    algorithm: HashAlgorithm,
+) -> KushnResult<String> {
+    if format != OutputFormat::Json {
+        let output = serialize_entries(entries, format)?;
+        write_output(path, &output)?;
+        return Ok(calculate_hash_reader(output.as_bytes(), algorithm)?);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = io::BufWriter::new(fs::File::create(path)?);
+    write_json_entries(&mut writer, entries)?;
+    writer.flush()?;
+    Ok(calculate_file_hash_with(path, algorithm)?)
+}
+
+fn write_output(path: &Path, output: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = io::BufWriter::new(fs::File::create(path)?);
+    writer.write_all(output.as_bytes())?;
+    writer.flush()
 }
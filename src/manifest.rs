@@ -0,0 +1,642 @@
+use std::borrow::{Borrow, Cow};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use serde::ser::{SerializeSeq, Serializer as _};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::hash::{calculate_hash_reader, HashAlgorithm};
+use crate::options::SymlinkPolicy;
+use crate::paths::normalize_path;
+use crate::{FileHash, KushnError, KushnResult, Warning};
+
+pub fn sort_entries(entries: &mut [FileHash]) {
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+}
+
+/// Serializes entries sorted by path, so that committed manifests only change
+/// on the lines belonging to files that actually changed.
+pub fn to_stable_json(entries: &[FileHash]) -> KushnResult<String> {
+    let mut sorted = entries.to_vec();
+    sort_entries(&mut sorted);
+    Ok(serde_json::to_string_pretty(&sorted)?)
+}
+
+/// Normalizes entries so the manifest depends only on the scanned content: sorted
+/// by path, and without the targets resolved by
+/// [`SymlinkPolicy::FollowAndRecord`], which are absolute paths of the scanning
+/// host, modification times, which depend on when the tree was checked out, or
+/// owner and group ids, which differ between hosts. `symlinks` is the policy the
+/// entries were scanned with; the targets recorded by
+/// [`SymlinkPolicy::HashLinkTarget`] are what the links contain, so they stay.
+pub fn make_reproducible(entries: &mut [FileHash], symlinks: SymlinkPolicy) {
+    sort_entries(entries);
+    for entry in entries {
+        if symlinks == SymlinkPolicy::FollowAndRecord {
+            entry.link_target = None;
+        }
+        entry.modified = None;
+        entry.uid = None;
+        entry.gid = None;
+    }
+}
+
+/// Removes the first `count` components from every entry path, as `tar
+/// --strip-components` does, so 2 turns `build/output/src/lib.rs` into
+/// `src/lib.rs`. Entries with no more than `count` components are dropped, or,
+/// with `strict`, fail with [`KushnError::TooFewComponents`] and leave `entries`
+/// unchanged. Paths that end up the same are all kept.
+pub fn strip_components(
+    entries: &mut Vec<FileHash>,
+    count: usize,
+    strict: bool,
+) -> KushnResult<()> {
+    if count == 0 {
+        return Ok(());
+    }
+    if strict {
+        if let Some(entry) = entries
+            .iter()
+            .find(|entry| entry.path.split('/').count() <= count)
+        {
+            return Err(KushnError::TooFewComponents {
+                path: entry.path.clone(),
+                count,
+            });
+        }
+    }
+    entries.retain_mut(|entry| match entry.path.splitn(count + 1, '/').nth(count) {
+        Some(rest) if !rest.is_empty() => {
+            entry.path = rest.to_owned();
+            true
+        }
+        _ => false,
+    });
+    Ok(())
+}
+
+/// Keys entries by path in path order, for formats that are maps rather than
+/// arrays. Fails with [`KushnError::DuplicatePath`] if a path occurs twice.
+pub fn to_ordered_map(entries: &[FileHash]) -> KushnResult<BTreeMap<String, FileHash>> {
+    let mut map = BTreeMap::new();
+    for entry in entries {
+        if map.insert(entry.path.clone(), entry.clone()).is_some() {
+            return Err(KushnError::DuplicatePath(entry.path.clone()));
+        }
+    }
+    Ok(map)
+}
+
+#[derive(Default)]
+struct TreeNode<'a> {
+    children: BTreeMap<&'a str, TreeNode<'a>>,
+    hash: Option<&'a str>,
+    is_dir: bool,
+}
+
+fn render_tree(node: &TreeNode, depth: usize, output: &mut String) {
+    for (name, child) in &node.children {
+        let indent = "  ".repeat(depth);
+        if let Some(hash) = child.hash {
+            let short_hash = hash.get(..8).unwrap_or(hash);
+            output.push_str(&format!("{}{}  {}\n", indent, name, short_hash));
+        }
+        if child.is_dir || !child.children.is_empty() {
+            output.push_str(&format!("{}{}/\n", indent, name));
+            render_tree(child, depth + 1, output);
+        }
+    }
+}
+
+pub fn to_tree_string(entries: &[FileHash]) -> String {
+    let mut root = TreeNode::default();
+    for entry in entries {
+        let mut node = &mut root;
+        for segment in entry.path.split('/').filter(|segment| !segment.is_empty()) {
+            node = node.children.entry(segment).or_default();
+        }
+        if entry.is_dir {
+            node.is_dir = true;
+        } else {
+            node.hash = Some(&entry.hash);
+        }
+    }
+
+    let mut output = String::new();
+    render_tree(&root, 0, &mut output);
+    output
+}
+
+/// Groups entries into nested objects keyed by path segment, with each file's
+/// hash as the leaf value and empty directories as empty objects. Only paths
+/// and hashes are kept.
+pub fn to_nested_json(entries: &[FileHash]) -> Value {
+    let mut root = Map::new();
+    for entry in entries {
+        let segments: Vec<&str> = entry
+            .path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        let Some((file_name, directories)) = segments.split_last() else {
+            continue;
+        };
+
+        let mut node = &mut root;
+        for directory in directories {
+            let child = node
+                .entry(directory.to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if !child.is_object() {
+                *child = Value::Object(Map::new());
+            }
+            node = child.as_object_mut().expect("directory node is an object");
+        }
+        if entry.is_dir {
+            node.entry(file_name.to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+        } else {
+            node.insert(file_name.to_string(), Value::String(entry.hash.clone()));
+        }
+    }
+    Value::Object(root)
+}
+
+fn flatten_nested(
+    node: &Map<String, Value>,
+    prefix: &str,
+    entries: &mut Vec<FileHash>,
+) -> KushnResult<()> {
+    for (name, value) in node {
+        let path = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        match value {
+            Value::String(hash) => entries.push(FileHash::new(path, hash.clone())),
+            Value::Object(children) if children.is_empty() => entries.push(FileHash {
+                is_dir: true,
+                ..FileHash::new(path, String::new())
+            }),
+            Value::Object(children) => flatten_nested(children, &path, entries)?,
+            _ => return Err(KushnError::InvalidManifest(path)),
+        }
+    }
+    Ok(())
+}
+
+/// Inverse of [`to_nested_json`]. Entries come back sorted by path.
+pub fn from_nested_json(value: &Value) -> KushnResult<Vec<FileHash>> {
+    let root = value
+        .as_object()
+        .ok_or_else(|| KushnError::InvalidManifest(String::new()))?;
+    let mut entries = Vec::new();
+    flatten_nested(root, "", &mut entries)?;
+    sort_entries(&mut entries);
+    Ok(entries)
+}
+
+pub const CYCLONEDX_SPEC_VERSION: &str = "1.5";
+
+fn cyclonedx_algorithm(algorithm: HashAlgorithm) -> Option<&'static str> {
+    match algorithm {
+        HashAlgorithm::Sha256 => Some("SHA-256"),
+        HashAlgorithm::Sha512 => Some("SHA-512"),
+        HashAlgorithm::Sha1 => Some("SHA-1"),
+        HashAlgorithm::Md5 => Some("MD5"),
+        HashAlgorithm::Blake3 => Some("BLAKE3"),
+        HashAlgorithm::Crc32 | HashAlgorithm::XxHash64 => None,
+    }
+}
+
+/// Minimal CycloneDX SBOM listing every entry as a `file` component with its
+/// digest, for feeding the manifest into SBOM tooling. CycloneDX has no name
+/// for the non-cryptographic checksums, so such components list no hashes.
+pub fn to_cyclonedx(entries: &[FileHash]) -> Value {
+    let components: Vec<Value> = entries
+        .iter()
+        .map(|entry| {
+            let hashes: Vec<Value> = cyclonedx_algorithm(entry.algorithm.unwrap_or_default())
+                .map(|alg| json!({ "alg": alg, "content": entry.hash }))
+                .into_iter()
+                .collect();
+            json!({
+                "type": "file",
+                "name": entry.path,
+                "hashes": hashes,
+            })
+        })
+        .collect();
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": CYCLONEDX_SPEC_VERSION,
+        "version": 1,
+        "components": components,
+    })
+}
+
+/// Identifies Kushn manifests in the `$schema` field of [`Manifest`].
+pub const MANIFEST_SCHEMA: &str = "urn:kushn:manifest";
+
+/// Version of the [`Manifest`] shape, bumped whenever fields change meaning or a
+/// required field is added.
+pub const MANIFEST_SCHEMA_VERSION: u32 = 1;
+
+/// Entries wrapped in an object naming the schema and its version, so consumers
+/// can pick a validator before reading `files`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(rename = "$schema")]
+    pub schema: String,
+    pub schema_version: u32,
+    /// Digest all files were hashed with; left out when they differ. Entries
+    /// without an algorithm of their own are read as hashed with this one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<HashAlgorithm>,
+    pub files: Vec<FileHash>,
+}
+
+impl Manifest {
+    pub fn new(files: Vec<FileHash>) -> Self {
+        let mut algorithms = files
+            .iter()
+            .map(|entry| entry.algorithm.unwrap_or_default());
+        let first = algorithms.next().unwrap_or_default();
+        let algorithm = algorithms
+            .all(|algorithm| algorithm == first)
+            .then_some(first);
+        Manifest {
+            schema: MANIFEST_SCHEMA.to_owned(),
+            schema_version: MANIFEST_SCHEMA_VERSION,
+            algorithm,
+            files,
+        }
+    }
+}
+
+/// Reads a manifest in any of the layouts Kushn writes: a [`Manifest`], a bare
+/// array of entries as written before manifests were versioned, or nested
+/// objects. A schema version newer than [`MANIFEST_SCHEMA_VERSION`] is read
+/// as far as it is understood and reported as
+/// [`Warning::UnknownSchemaVersion`] rather than rejected.
+pub fn load_manifest<P: AsRef<Path>>(path: P) -> KushnResult<(Vec<FileHash>, Vec<Warning>)> {
+    let path = path.as_ref();
+    parse_manifest(&fs::read_to_string(path)?, path)
+}
+
+/// Same as [`load_manifest`] for a manifest already read into `contents`, e.g.
+/// fetched over the network. Warnings name `source` as where it came from.
+pub fn parse_manifest<P: AsRef<Path>>(
+    contents: &str,
+    source: P,
+) -> KushnResult<(Vec<FileHash>, Vec<Warning>)> {
+    let value: Value = serde_json::from_str(contents)?;
+    let mut warnings = Vec::new();
+    let entries = match value {
+        Value::Array(_) => serde_json::from_value(value)?,
+        Value::Object(ref object) if object.contains_key("schema_version") => {
+            let manifest: Manifest = serde_json::from_value(value)?;
+            if manifest.schema_version > MANIFEST_SCHEMA_VERSION {
+                warnings.push(Warning::UnknownSchemaVersion(
+                    source.as_ref().to_path_buf(),
+                    manifest.schema_version,
+                ));
+            }
+            let algorithm = manifest
+                .algorithm
+                .filter(|&algorithm| algorithm != HashAlgorithm::Sha256);
+            manifest
+                .files
+                .into_iter()
+                .map(|entry| FileHash {
+                    algorithm: entry.algorithm.or(algorithm),
+                    ..entry
+                })
+                .collect()
+        }
+        _ => from_nested_json(&value)?,
+    };
+    Ok((entries, warnings))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Nested,
+    /// A [`Manifest`] with schema id and version around the entries.
+    Wrapped,
+    /// A `hash,path` header followed by one such line per entry.
+    Csv,
+    /// `<hash>  <path>` lines as written by coreutils `sha256sum`, so the output
+    /// can be checked with `sha256sum -c`.
+    Sfv,
+}
+
+impl OutputFormat {
+    /// The format a manifest file is meant to be in going by its extension,
+    /// matched regardless of case: `.json`, `.csv` or `.sfv`. `None` for any
+    /// other extension or none.
+    pub fn from_extension<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(OutputFormat::Json),
+            "csv" => Some(OutputFormat::Csv),
+            "sfv" => Some(OutputFormat::Sfv),
+            _ => None,
+        }
+    }
+
+    /// Whether the format is one of the JSON layouts, which carry every field of
+    /// an entry, unlike the line formats.
+    pub fn is_json(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Json | OutputFormat::Nested | OutputFormat::Wrapped
+        )
+    }
+}
+
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+fn sums_line(entry: &FileHash) -> String {
+    if entry.path.contains(['\\', '\n']) {
+        let path = entry.path.replace('\\', "\\\\").replace('\n', "\\n");
+        format!("\\{}  {}\n", entry.hash, path)
+    } else {
+        format!("{}  {}\n", entry.hash, entry.path)
+    }
+}
+
+pub fn serialize_entries(entries: &[FileHash], format: OutputFormat) -> KushnResult<String> {
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(entries)?),
+        OutputFormat::Nested => Ok(serde_json::to_string_pretty(&to_nested_json(entries))?),
+        OutputFormat::Wrapped => Ok(serde_json::to_string_pretty(&Manifest::new(
+            entries.to_vec(),
+        ))?),
+        OutputFormat::Csv => {
+            let mut output = String::from("hash,path\n");
+            for entry in entries {
+                output.push_str(&entry.hash);
+                output.push(',');
+                output.push_str(&csv_field(&entry.path));
+                output.push('\n');
+            }
+            Ok(output)
+        }
+        // Checksum lists have no way to express a directory.
+        OutputFormat::Sfv => Ok(entries
+            .iter()
+            .filter(|entry| !entry.is_dir)
+            .map(sums_line)
+            .collect()),
+    }
+}
+
+/// Writes entries as a pretty JSON array, one at a time as `entries` yields
+/// them, so that a manifest never has to be held in memory as a whole. The
+/// output is the same as [`serialize_entries`] with [`OutputFormat::Json`].
+/// Pairs with [`process_directory_iter`](crate::process_directory_iter) for
+/// huge trees.
+pub fn write_json_entries<W, I>(writer: W, entries: I) -> KushnResult<()>
+where
+    W: Write,
+    I: IntoIterator,
+    I::Item: Borrow<FileHash>,
+{
+    let mut serializer = serde_json::Serializer::pretty(writer);
+    let mut seq = serializer.serialize_seq(None)?;
+    for entry in entries {
+        seq.serialize_element(entry.borrow())?;
+    }
+    seq.end()?;
+    Ok(())
+}
+
+/// Serializes `entries` once and returns the output together with an entry for
+/// it at `manifest_path`, hashed over exactly those bytes. A manifest cannot
+/// list its own hash, so the entry is meant to be published next to it.
+pub fn build_manifest(
+    entries: &[FileHash],
+    format: OutputFormat,
+    manifest_path: &str,
+    algorithm: HashAlgorithm,
+) -> KushnResult<(String, FileHash)> {
+    let output = serialize_entries(entries, format)?;
+    let hash = calculate_hash_reader(output.as_bytes(), algorithm)?;
+    let entry = FileHash {
+        algorithm: (algorithm != HashAlgorithm::Sha256).then_some(algorithm),
+        ..FileHash::new(normalize_path(Path::new(manifest_path)), hash)
+    };
+    Ok((output, entry))
+}
+
+fn json_string_len(value: &str) -> usize {
+    let escaped: usize = value
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => 2,
+            c if (c as u32) < 0x20 => 6,
+            c => c.len_utf8(),
+        })
+        .sum();
+    escaped + 2
+}
+
+/// Approximates the serialized size of `entries` without serializing them. The
+/// estimate errs on the high side, so it is safe for pre-allocating buffers.
+pub fn estimate_manifest_bytes(entries: &[FileHash], format: OutputFormat) -> usize {
+    match format {
+        OutputFormat::Json => {
+            if entries.is_empty() {
+                return 2;
+            }
+            let entry_bytes: usize = entries
+                .iter()
+                .map(|entry| {
+                    // `  {\n`, `    "path": ` + `,\n`, `    "hash": ` + `\n`, `  },\n`
+                    let mut size = 4 + 14 + json_string_len(&entry.path);
+                    size += 13 + json_string_len(&entry.hash) + 5;
+                    if let Some(link_target) = &entry.link_target {
+                        size += 21 + json_string_len(link_target);
+                    }
+                    if let Some(content_type) = &entry.content_type {
+                        size += 22 + json_string_len(content_type);
+                    }
+                    // `,\n    "uid": ` plus at most 10 digits, likewise for `gid`.
+                    if entry.uid.is_some() {
+                        size += 13 + 10;
+                    }
+                    if entry.gid.is_some() {
+                        size += 13 + 10;
+                    }
+                    if let Some(algorithm) = entry.algorithm {
+                        size += 19 + json_string_len(algorithm.name());
+                    }
+                    // `,\n    "size": ` plus at most 20 digits.
+                    if entry.size.is_some() {
+                        size += 14 + 20;
+                    }
+                    // `,\n    "modified": ` plus a quoted timestamp with nanoseconds.
+                    if entry.modified.is_some() {
+                        size += 18 + 32;
+                    }
+                    // `,\n    "is_dir": true`
+                    if entry.is_dir {
+                        size += 20;
+                    }
+                    size
+                })
+                .sum();
+            entry_bytes + 2
+        }
+        OutputFormat::Nested => {
+            let entry_bytes: usize = entries
+                .iter()
+                .map(|entry| {
+                    // Every entry is counted as if it opened and closed all of its
+                    // directories itself: `<indent>"segment": {\n` and `<indent>},\n`.
+                    let segments: Vec<&str> = entry.path.split('/').collect();
+                    let mut size = 0;
+                    for (depth, segment) in segments.iter().enumerate() {
+                        let indent = 2 * (depth + 1);
+                        size += indent + json_string_len(segment) + 2;
+                        if depth + 1 < segments.len() {
+                            size += 2 + indent + 3;
+                        }
+                    }
+                    size + json_string_len(&entry.hash) + 2
+                })
+                .sum();
+            entry_bytes + 4
+        }
+        OutputFormat::Wrapped => {
+            // The header lines, plus two more spaces of indentation on each of the
+            // at most 12 lines an entry takes.
+            let header = 100 + json_string_len(MANIFEST_SCHEMA);
+            estimate_manifest_bytes(entries, OutputFormat::Json) + entries.len() * 24 + header
+        }
+        // Quoting or escaping at most doubles a path and adds two characters.
+        OutputFormat::Csv => {
+            let entry_bytes: usize = entries
+                .iter()
+                .map(|entry| entry.hash.len() + 2 * entry.path.len() + 4)
+                .sum();
+            entry_bytes + 10
+        }
+        OutputFormat::Sfv => entries
+            .iter()
+            .map(|entry| entry.hash.len() + 2 * entry.path.len() + 4)
+            .sum(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ManifestSummary {
+    pub file_count: usize,
+    /// Number of distinct contents.
+    pub unique_hashes: usize,
+    /// Copies beyond the first of each content, i.e. `file_count - unique_hashes`.
+    pub duplicate_count: usize,
+    /// Sum of file sizes, only known when entries record their size.
+    pub total_bytes: Option<u64>,
+}
+
+/// Counts the files of a manifest; entries of empty directories are left out.
+pub fn summarize_manifest(entries: &[FileHash]) -> ManifestSummary {
+    let files: Vec<&FileHash> = entries.iter().filter(|entry| !entry.is_dir).collect();
+    let unique_hashes = files
+        .iter()
+        .map(|entry| entry.hash.as_str())
+        .collect::<HashSet<_>>()
+        .len();
+    ManifestSummary {
+        file_count: files.len(),
+        unique_hashes,
+        duplicate_count: files.len() - unique_hashes,
+        total_bytes: files.iter().map(|entry| entry.size).sum(),
+    }
+}
+
+/// Single digest identifying a whole tree. Entries are sorted by path and each
+/// contributes `path || 0x00 || hash || '\n'` to one SHA-256, so the result is
+/// independent of traversal order and platform.
+pub fn directory_root_hash(entries: &[FileHash]) -> String {
+    let mut sorted: Vec<&FileHash> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut hasher = Sha256::new();
+    for entry in sorted {
+        hasher.update(entry.path.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(entry.hash.as_bytes());
+        hasher.update(b"\n");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+fn unescape_sums_path(path: &str) -> String {
+    let mut unescaped = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('\\')) => {
+                chars.next();
+                unescaped.push('\\');
+            }
+            ('\\', Some('n')) => {
+                chars.next();
+                unescaped.push('\n');
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Parses `sha256sum`-style output: `<hash>  <path>` per line, or `<hash> *<path>`
+/// for binary mode. Lines starting with `\\` carry escaped backslashes and
+/// newlines in the path, as written by coreutils. A leading `./` is dropped so
+/// paths line up with scanned entries.
+pub fn parse_coreutils_sums(contents: &str) -> KushnResult<Vec<FileHash>> {
+    let mut entries = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (escaped, line) = match line.strip_prefix('\\') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let invalid = || KushnError::InvalidSumsLine { line: index + 1 };
+
+        let (hash, rest) = line.split_once(' ').ok_or_else(invalid)?;
+        let path = rest
+            .strip_prefix(' ')
+            .or_else(|| rest.strip_prefix('*'))
+            .ok_or_else(invalid)?;
+        if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) || path.is_empty() {
+            return Err(invalid());
+        }
+
+        let path = if escaped {
+            unescape_sums_path(path)
+        } else {
+            path.to_owned()
+        };
+        let path = path.strip_prefix("./").map(str::to_owned).unwrap_or(path);
+        entries.push(FileHash::new(path, hash.to_ascii_lowercase()));
+    }
+    Ok(entries)
+}
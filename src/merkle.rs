@@ -0,0 +1,224 @@
+//! Merkle tree over a manifest, for proving a single file's inclusion without
+//! shipping the whole manifest.
+//!
+//! Construction:
+//! - entries are sorted by path (byte-wise);
+//! - leaf = SHA-256(`0x00` || path || `0x00` || hex hash);
+//! - node = SHA-256(`0x01` || left || right), over the raw 32-byte child digests;
+//! - a level with an odd number of nodes promotes its last node unchanged;
+//! - the root of an empty manifest is SHA-256 of the empty string.
+//!
+//! The distinct leaf and node prefixes keep a leaf from being passed off as an
+//! inner node.
+
+use std::collections::BTreeSet;
+
+use sha2::{Digest, Sha256};
+
+use crate::hash::{decode_hex, encode_hex};
+use crate::FileHash;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+type Node = [u8; 32];
+
+pub(crate) fn leaf_hash(entry: &FileHash) -> Node {
+    leaf_digest(&entry.path, &entry.hash)
+}
+
+fn leaf_digest(path: &str, hash: &str) -> Node {
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    hasher.update(path.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(hash.as_bytes());
+    hasher.finalize().into()
+}
+
+pub(crate) fn node_hash(left: &Node, right: &Node) -> Node {
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn sorted_leaves(entries: &[FileHash]) -> Vec<(&str, Node)> {
+    let mut sorted: Vec<&FileHash> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.path.cmp(&b.path));
+    sorted
+        .into_iter()
+        .map(|entry| (entry.path.as_str(), leaf_hash(entry)))
+        .collect()
+}
+
+fn next_level(level: &[Node]) -> Vec<Node> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+pub fn merkle_root(entries: &[FileHash]) -> String {
+    let mut level: Vec<Node> = sorted_leaves(entries)
+        .into_iter()
+        .map(|(_, leaf)| leaf)
+        .collect();
+    if level.is_empty() {
+        return encode_hex(&Sha256::digest(b""));
+    }
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    encode_hex(&level[0])
+}
+
+/// Sibling hashes from the leaf of `path` up to the root, each tagged with the
+/// side the sibling sits on. Levels where the node was promoted contribute no
+/// step. Returns `None` if `path` is not in the manifest.
+pub fn merkle_proof(entries: &[FileHash], path: &str) -> Option<Vec<(Side, String)>> {
+    let leaves = sorted_leaves(entries);
+    let mut index = leaves
+        .iter()
+        .position(|(leaf_path, _)| *leaf_path == path)?;
+    let mut level: Vec<Node> = leaves.into_iter().map(|(_, leaf)| leaf).collect();
+
+    let mut proof = Vec::new();
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            let side = if sibling < index {
+                Side::Left
+            } else {
+                Side::Right
+            };
+            proof.push((side, encode_hex(&level[sibling])));
+        }
+        level = next_level(&level);
+        index /= 2;
+    }
+    Some(proof)
+}
+
+pub fn verify_merkle_proof(entry: &FileHash, proof: &[(Side, String)], root: &str) -> bool {
+    let mut current = leaf_hash(entry);
+    for (side, sibling) in proof {
+        let sibling: Node = match decode_hex(sibling).and_then(|bytes| bytes.try_into().ok()) {
+            Some(sibling) => sibling,
+            None => return false,
+        };
+        current = match side {
+            Side::Left => node_hash(&sibling, &current),
+            Side::Right => node_hash(&current, &sibling),
+        };
+    }
+    encode_hex(&current) == root
+}
+
+/// Keeps every level of the tree so the root can follow changes to a manifest
+/// without rebuilding it, e.g. in a watch loop. The root always equals
+/// [`merkle_root`] over the current entries.
+///
+/// Changing the hash of a known path rehashes only the nodes above it, O(log n).
+/// Adding or removing a path shifts the leaves after it, so the inner levels are
+/// rebuilt from the kept leaf digests: O(n) node hashes, but no leaf is rehashed.
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    paths: Vec<String>,
+    levels: Vec<Vec<Node>>,
+}
+
+impl MerkleTree {
+    pub fn new(entries: &[FileHash]) -> Self {
+        let (paths, leaves) = sorted_leaves(entries)
+            .into_iter()
+            .map(|(path, leaf)| (path.to_owned(), leaf))
+            .unzip();
+        let mut tree = MerkleTree {
+            paths,
+            levels: vec![leaves],
+        };
+        tree.rebuild();
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn root(&self) -> String {
+        match self.levels.last().and_then(|level| level.first()) {
+            Some(root) => encode_hex(root),
+            None => encode_hex(&Sha256::digest(b"")),
+        }
+    }
+
+    /// Applies `(path, Some(hash))` for added or changed files and `(path, None)`
+    /// for removed ones, then returns the new root.
+    pub fn update(&mut self, changes: &[(String, Option<String>)]) -> String {
+        let mut dirty = BTreeSet::new();
+        let mut reshaped = false;
+        for (path, hash) in changes {
+            let leaves = &mut self.levels[0];
+            match (self.paths.binary_search(path), hash) {
+                (Ok(index), Some(hash)) => {
+                    leaves[index] = leaf_digest(path, hash);
+                    dirty.insert(index);
+                }
+                (Ok(index), None) => {
+                    self.paths.remove(index);
+                    leaves.remove(index);
+                    reshaped = true;
+                }
+                (Err(index), Some(hash)) => {
+                    self.paths.insert(index, path.clone());
+                    leaves.insert(index, leaf_digest(path, hash));
+                    reshaped = true;
+                }
+                (Err(_), None) => {}
+            }
+        }
+
+        if reshaped {
+            self.rebuild();
+        } else {
+            self.refresh(dirty);
+        }
+        self.root()
+    }
+
+    fn rebuild(&mut self) {
+        self.levels.truncate(1);
+        while self.levels[self.levels.len() - 1].len() > 1 {
+            let next = next_level(&self.levels[self.levels.len() - 1]);
+            self.levels.push(next);
+        }
+    }
+
+    fn refresh(&mut self, mut dirty: BTreeSet<usize>) {
+        for depth in 1..self.levels.len() {
+            let parents: BTreeSet<usize> = dirty.iter().map(|index| index / 2).collect();
+            for &parent in &parents {
+                let children = &self.levels[depth - 1];
+                let node = match children.get(2 * parent + 1) {
+                    Some(right) => node_hash(&children[2 * parent], right),
+                    None => children[2 * parent],
+                };
+                self.levels[depth][parent] = node;
+            }
+            dirty = parents;
+        }
+    }
+}
@@ -0,0 +1,582 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::cache::CacheStore;
+use crate::hash::{Encoding, HashAlgorithm, Hasher};
+use crate::paths::normalize_path;
+use crate::patterns::IGNORE_FILE_NAME;
+use crate::walk::Traversal;
+use crate::{KushnError, KushnResult};
+
+/// How symlinks met during a scan are treated. The default is `Skip`: following
+/// links can escape the scan root or loop, so it has to be requested explicitly.
+/// Versions before 0.2 always followed links; `Follow` keeps that behaviour and,
+/// like `FollowAndRecord`, reports links back to a parent directory as loops
+/// instead of walking them again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    Follow,
+    /// Follows symlinks like `Follow` and records the resolved target path of
+    /// each link in [`FileHash::link_target`](crate::FileHash::link_target).
+    FollowAndRecord,
+    #[default]
+    Skip,
+    /// Does not follow symlinks but records each one as an entry of its own,
+    /// hashing the link's target path instead of any content, so retargeting a
+    /// link changes the manifest. The unresolved target is stored in
+    /// [`FileHash::link_target`](crate::FileHash::link_target).
+    HashLinkTarget,
+}
+
+/// How entry paths are made relative to the scan root. Either way the root itself
+/// is never an entry, `.` components are dropped and paths never start with a
+/// separator, so `dir`, `dir/` and `./dir` give the same manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BaseStripping {
+    /// Paths are relative to the root: `a.txt`, `sub/b.txt`. Scanning a single
+    /// file therefore yields no entry.
+    #[default]
+    Exclusive,
+    /// Paths start with the root's own name: `dir/a.txt`, `dir/sub/b.txt`, or
+    /// just `a.txt` when the root is that file. The name is taken from the
+    /// canonical root, so `.` is named after the directory it refers to.
+    Inclusive,
+}
+
+pub const HARDENED_MAX_FILES: usize = 1_000_000;
+
+/// Size from which [`ProcessOptions::with_mmap`] maps files instead of reading them.
+pub const MMAP_MIN_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct ProcessOptions {
+    pub(crate) ignore: Vec<String>,
+    pub(crate) symlinks: SymlinkPolicy,
+    pub(crate) same_file_system: bool,
+    pub(crate) skip_special_files: bool,
+    pub(crate) continue_on_error: bool,
+    pub(crate) max_files: Option<usize>,
+    pub(crate) max_dirs: Option<usize>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) path_binding: bool,
+    pub(crate) detect_uniform: bool,
+    pub(crate) cache: Option<CacheStore>,
+    pub(crate) max_file_size: Option<u64>,
+    pub(crate) min_size: Option<u64>,
+    pub(crate) empty_dirs: bool,
+    pub(crate) max_size: Option<u64>,
+    pub(crate) content_type: bool,
+    pub(crate) traversal: Traversal,
+    pub(crate) max_age: Option<Duration>,
+    pub(crate) sample: Option<(f64, u64)>,
+    pub(crate) allow_empty: bool,
+    pub(crate) dot_prefix: bool,
+    pub(crate) base_stripping: BaseStripping,
+    pub(crate) base_dir: Option<PathBuf>,
+    pub(crate) decompress_extensions: Vec<String>,
+    pub(crate) dedup_content: bool,
+    pub(crate) ownership: bool,
+    pub(crate) algorithm: HashAlgorithm,
+    pub(crate) encoding: Encoding,
+    pub(crate) truncate: Option<usize>,
+    pub(crate) mmap: bool,
+    pub(crate) file_metadata: bool,
+    pub(crate) count_first: bool,
+    pub(crate) sorted: bool,
+    pub(crate) nested_ignore: bool,
+    pub(crate) ignore_file_name: String,
+    pub(crate) include: Vec<String>,
+    pub(crate) case_insensitive: bool,
+    pub(crate) case_insensitive_paths: bool,
+    pub(crate) excluded_files: Vec<PathBuf>,
+    pub(crate) threads: Option<usize>,
+    pub(crate) io_threads: Option<usize>,
+    pub(crate) hash_threads: Option<usize>,
+    // Set by `process_directory_with_errors`, which reports failures such as
+    // denied access as errors rather than warnings.
+    pub(crate) failures_as_errors: bool,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        ProcessOptions {
+            ignore: Vec::new(),
+            symlinks: SymlinkPolicy::Skip,
+            same_file_system: false,
+            skip_special_files: false,
+            continue_on_error: false,
+            max_files: None,
+            max_dirs: None,
+            max_depth: None,
+            path_binding: false,
+            detect_uniform: false,
+            cache: None,
+            max_file_size: None,
+            min_size: None,
+            empty_dirs: false,
+            max_size: None,
+            content_type: false,
+            traversal: Traversal::DepthFirst,
+            max_age: None,
+            sample: None,
+            allow_empty: true,
+            dot_prefix: false,
+            base_stripping: BaseStripping::Exclusive,
+            base_dir: None,
+            decompress_extensions: Vec::new(),
+            dedup_content: false,
+            ownership: false,
+            algorithm: HashAlgorithm::Sha256,
+            encoding: Encoding::HexLower,
+            truncate: None,
+            mmap: false,
+            file_metadata: false,
+            count_first: false,
+            sorted: true,
+            nested_ignore: false,
+            ignore_file_name: IGNORE_FILE_NAME.to_owned(),
+            include: Vec::new(),
+            case_insensitive: false,
+            case_insensitive_paths: false,
+            excluded_files: Vec::new(),
+            threads: None,
+            io_threads: None,
+            hash_threads: None,
+            failures_as_errors: false,
+        }
+    }
+}
+
+impl ProcessOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn reads_content(&self) -> bool {
+        self.path_binding || self.detect_uniform || self.content_type
+    }
+
+    pub(crate) fn uses_cache(&self) -> bool {
+        !self.reads_content() && self.algorithm == HashAlgorithm::Sha256
+    }
+
+    pub(crate) fn decompresses(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                self.decompress_extensions
+                    .iter()
+                    .any(|candidate| candidate == extension)
+            })
+    }
+
+    /// Safe baseline for scanning untrusted trees: symlinks are skipped, the scan
+    /// stays on the root's filesystem, FIFOs/sockets/devices are never opened,
+    /// unreadable entries are collected instead of aborting the scan, and the
+    /// number of hashed files is capped at [`HARDENED_MAX_FILES`].
+    pub fn hardened() -> Self {
+        ProcessOptions {
+            symlinks: SymlinkPolicy::Skip,
+            same_file_system: true,
+            skip_special_files: true,
+            continue_on_error: true,
+            max_files: Some(HARDENED_MAX_FILES),
+            ..Self::default()
+        }
+    }
+
+    /// Glob patterns excluding files and directories. A pattern matches files by
+    /// path suffix at any depth and directories by their path from the root; a
+    /// trailing `/` makes it match directories only. Patterns apply in order and
+    /// the last one matching a file or any of its parent directories wins, so a
+    /// leading `!` re-includes what earlier patterns excluded: `target/` followed
+    /// by `!target/keep.bin` keeps that one file. Excluded directories are still
+    /// walked while a `!` pattern could match below them.
+    pub fn with_ignore(mut self, ignore: Vec<String>) -> Self {
+        self.ignore = ignore;
+        self
+    }
+
+    /// Also reads an ignore file, [`IGNORE_FILE_NAME`] unless changed with
+    /// [`with_ignore_file_name`](Self::with_ignore_file_name), from every
+    /// subdirectory the walk enters. Its patterns are relative to that directory,
+    /// apply only below it and take precedence over the patterns of enclosing
+    /// directories. The scanned directory's own file is not read; pass its
+    /// patterns to [`with_ignore`](Self::with_ignore).
+    pub fn with_nested_ignore(mut self, enabled: bool) -> Self {
+        self.nested_ignore = enabled;
+        self
+    }
+
+    /// Name of the ignore files read with
+    /// [`with_nested_ignore`](Self::with_nested_ignore).
+    pub fn with_ignore_file_name(mut self, name: String) -> Self {
+        self.ignore_file_name = name;
+        self
+    }
+
+    /// Only hashes files matching at least one of these glob patterns, checked
+    /// after the ignore patterns. Patterns match by path suffix at any depth, like
+    /// ignore patterns for files, so `*.rs` selects Rust files everywhere. An
+    /// empty list includes every file.
+    pub fn with_include(mut self, include: Vec<String>) -> Self {
+        self.include = include;
+        self
+    }
+
+    /// Never hashes these files, given relative to the scanned directory or as
+    /// absolute paths below it. Unlike ignore patterns they name one file each,
+    /// e.g. the manifest a scan is about to write into the directory it scans,
+    /// which would otherwise show up as an entry of the next scan.
+    pub fn with_excluded_files(mut self, files: Vec<PathBuf>) -> Self {
+        self.excluded_files = files;
+        self
+    }
+
+    /// Matches ignore and include patterns regardless of case, so `*.TXT`
+    /// ignores `notes.txt`, as Windows users tend to expect. Off by default.
+    pub fn with_case_insensitive(mut self, enabled: bool) -> Self {
+        self.case_insensitive = enabled;
+        self
+    }
+
+    /// Matches files to manifest entries regardless of the case of their paths
+    /// when verifying, as on Windows or macOS where `README.md` and `readme.md`
+    /// are the same file. Reports keep the paths as written. Of manifest entries
+    /// differing only in case, all but the last are reported missing.
+    pub fn with_case_insensitive_paths(mut self, enabled: bool) -> Self {
+        self.case_insensitive_paths = enabled;
+        self
+    }
+
+    pub fn with_symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    pub fn with_same_file_system(mut self, enabled: bool) -> Self {
+        self.same_file_system = enabled;
+        self
+    }
+
+    pub fn with_skip_special_files(mut self, enabled: bool) -> Self {
+        self.skip_special_files = enabled;
+        self
+    }
+
+    pub fn with_continue_on_error(mut self, enabled: bool) -> Self {
+        self.continue_on_error = enabled;
+        self
+    }
+
+    pub fn with_max_files(mut self, max_files: Option<usize>) -> Self {
+        self.max_files = max_files;
+        self
+    }
+
+    /// Aborts the scan with [`KushnError::DirLimitExceeded`](crate::KushnError::DirLimitExceeded)
+    /// once more than `max_dirs` directories, the root included, have been walked.
+    pub fn with_max_dirs(mut self, max_dirs: Option<usize>) -> Self {
+        self.max_dirs = max_dirs;
+        self
+    }
+
+    /// Limits how many directory levels below the root are descended into: `0`
+    /// hashes only the files directly in the root, `1` also those in its
+    /// subdirectories, and so on. Ignore patterns still apply to what is left.
+    pub fn with_max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Folds each entry's relative path into its digest, see
+    /// [`calculate_path_bound_hash`](crate::calculate_path_bound_hash).
+    pub fn with_path_binding(mut self, enabled: bool) -> Self {
+        self.path_binding = enabled;
+        self
+    }
+
+    /// Reports files consisting of a single repeated byte (e.g. zero-filled
+    /// placeholders) in [`ScanReport::uniform_files`](crate::ScanReport::uniform_files).
+    pub fn with_detect_uniform(mut self, enabled: bool) -> Self {
+        self.detect_uniform = enabled;
+        self
+    }
+
+    /// Reuses hashes of files whose device, inode, size and mtime are unchanged
+    /// since they were recorded. The cache is bypassed when path binding,
+    /// uniform-byte detection or content-type detection is enabled, since those
+    /// need the file contents, and for algorithms other than SHA-256.
+    pub fn with_cache(mut self, cache: CacheStore) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Aborts the scan with [`KushnError::FileTooLarge`](crate::KushnError::FileTooLarge)
+    /// when a file larger than `max_file_size` bytes is encountered.
+    pub fn with_max_file_size(mut self, max_file_size: Option<u64>) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Also records directories without any entries, flagged with
+    /// [`FileHash::is_dir`](crate::FileHash::is_dir) and an empty hash, so that
+    /// verification notices when one is added or removed. A directory holding
+    /// only ignored files is not empty. Checksum lists written as
+    /// [`OutputFormat::Sfv`](crate::OutputFormat::Sfv) leave them out.
+    pub fn with_empty_dirs(mut self, enabled: bool) -> Self {
+        self.empty_dirs = enabled;
+        self
+    }
+
+    /// Skips files smaller than `min_size` bytes, e.g. generated stubs, without
+    /// reading them.
+    pub fn with_min_size(mut self, min_size: Option<u64>) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Skips files larger than `max_size` bytes, e.g. media blobs, without
+    /// reading them. Unlike [`with_max_file_size`](Self::with_max_file_size),
+    /// the scan goes on.
+    pub fn with_max_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Records each file's MIME type, sniffed from its magic bytes during the hash
+    /// read. Unrecognized content is reported as `application/octet-stream`.
+    pub fn with_content_type(mut self, enabled: bool) -> Self {
+        self.content_type = enabled;
+        self
+    }
+
+    /// Selects the directory traversal order. Breadth-first yields top-level files
+    /// first; both orders produce the same set of entries.
+    pub fn with_traversal(mut self, traversal: Traversal) -> Self {
+        self.traversal = traversal;
+        self
+    }
+
+    /// Skips files whose mtime is more than `max_age` before the start of the scan.
+    /// Files whose mtime cannot be read are kept.
+    pub fn with_max_age(mut self, max_age: Option<Duration>) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Hashes only a reproducible subset of roughly `fraction` of the files, for
+    /// quick spot checks of huge trees. Whether a file is picked depends only on
+    /// its relative path and `seed`, so the same seed selects the same files on
+    /// every run.
+    pub fn with_sample(mut self, fraction: f64, seed: u64) -> Self {
+        self.sample = Some((fraction.clamp(0.0, 1.0), seed));
+        self
+    }
+
+    /// When disabled, a scan that hashes no file at all fails with
+    /// [`KushnError::NoFilesFound`](crate::KushnError::NoFilesFound), which usually
+    /// means a wrong directory or over-broad ignore patterns.
+    pub fn with_allow_empty(mut self, enabled: bool) -> Self {
+        self.allow_empty = enabled;
+        self
+    }
+
+    /// Writes manifest paths as `./file.txt`, the way `find .` prints them. Only
+    /// the output changes: ignore patterns, sampling and path binding still see
+    /// the plain relative path.
+    pub fn with_dot_prefix(mut self, enabled: bool) -> Self {
+        self.dot_prefix = enabled;
+        self
+    }
+
+    pub fn with_base_stripping(mut self, base_stripping: BaseStripping) -> Self {
+        self.base_stripping = base_stripping;
+        self
+    }
+
+    /// Makes entry paths relative to `base_dir` rather than to the scanned
+    /// directory (or, for [`process_file_with`](crate::process_file_with), the
+    /// current directory), so the output does not depend on the caller's working
+    /// directory. Scanning `base/sub` then yields `sub/...` paths, wherever `base`
+    /// lives, so manifests of copies under different roots can be diffed. Ignore
+    /// patterns still match relative to the scanned directory, and the base takes
+    /// precedence over [`BaseStripping`]. Paths outside the base fail with
+    /// [`KushnError::OutsideBaseDir`](crate::KushnError::OutsideBaseDir).
+    pub fn with_base_dir<P: Into<PathBuf>>(mut self, base_dir: P) -> Self {
+        self.base_dir = Some(base_dir.into());
+        self
+    }
+
+    /// Hashes files with one of these extensions (e.g. `gz`, with or without the
+    /// dot) as their gzip-decompressed content, so the digest does not depend on
+    /// how the file was compressed. Files that fail to decompress are hashed as
+    /// stored and reported as [`Warning::DecompressionFailed`](crate::Warning::DecompressionFailed).
+    pub fn with_decompress_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.decompress_extensions = extensions
+            .into_iter()
+            .map(|extension| extension.trim_start_matches('.').to_owned())
+            .collect();
+        self
+    }
+
+    /// Keeps a single entry per distinct hash, the one with the lexicographically
+    /// smallest path, for a content-unique manifest. Applied once the walk is
+    /// complete, so streaming callbacks still see every file.
+    pub fn with_dedup_content(mut self, enabled: bool) -> Self {
+        self.dedup_content = enabled;
+        self
+    }
+
+    /// Sorts the collected entries by path, byte by byte, so that results do not
+    /// depend on the directory listing order of the filesystem. On by default;
+    /// turning it off keeps walk order and saves the sort on huge trees. Like
+    /// [`with_dedup_content`](Self::with_dedup_content), streaming callbacks still
+    /// see files in walk order.
+    pub fn with_sorted(mut self, enabled: bool) -> Self {
+        self.sorted = enabled;
+        self
+    }
+
+    /// Records each file's owner and group ids in
+    /// [`FileHash::uid`](crate::FileHash::uid) and [`FileHash::gid`](crate::FileHash::gid),
+    /// so ownership changes show up even when the content is unchanged. Has no
+    /// effect on non-Unix platforms.
+    pub fn with_ownership(mut self, enabled: bool) -> Self {
+        self.ownership = enabled;
+        self
+    }
+
+    /// Selects the digest used for file hashes. Entries hashed with anything but
+    /// SHA-256 record the algorithm in [`FileHash::algorithm`](crate::FileHash::algorithm).
+    pub fn with_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Writes [`FileHash::hash`](crate::FileHash::hash) in `encoding` instead of
+    /// lowercase hex. Manifests do not record the encoding, so verify with the
+    /// same one.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Keeps only the first `length` characters of each written digest, for
+    /// short identifiers shown to people. The full digest is still computed, but
+    /// a truncated one is far weaker against collisions and tampering: use it
+    /// for display only, never for integrity checks. Scans fail with
+    /// [`KushnError::InvalidArgument`](crate::KushnError::InvalidArgument) if `length` is zero or longer than the
+    /// digest.
+    pub fn with_truncate(mut self, length: Option<usize>) -> Self {
+        self.truncate = length;
+        self
+    }
+
+    /// Hashes files of at least [`MMAP_MIN_SIZE`] bytes from a memory map, as
+    /// [`calculate_file_hash_mmap`](crate::calculate_file_hash_mmap) does. Smaller
+    /// files are cheaper to read than to map.
+    pub fn with_mmap(mut self, enabled: bool) -> Self {
+        self.mmap = enabled;
+        self
+    }
+
+    /// Records each file's size and modification time in
+    /// [`FileHash::size`](crate::FileHash::size) and
+    /// [`FileHash::modified`](crate::FileHash::modified). Like the hash, they
+    /// describe the target of a followed symlink, not the link itself.
+    pub fn with_file_metadata(mut self, enabled: bool) -> Self {
+        self.file_metadata = enabled;
+        self
+    }
+
+    /// Walks the tree once before hashing to count its files, so that
+    /// [`process_directory_with_progress`](crate::process_directory_with_progress)
+    /// can report a total. Costs a second directory walk.
+    pub fn with_count_first(mut self, enabled: bool) -> Self {
+        self.count_first = enabled;
+        self
+    }
+
+    /// Number of threads [`hash_files_parallel`](crate::hash_files_parallel)
+    /// and [`process_directory_queued`](crate::process_directory_queued) hash
+    /// with; `None` uses one per logical core. Lower it on network filesystems,
+    /// where many concurrent reads slow each other down. `Some(1)` makes
+    /// `hash_files_parallel` hash the files one after another on the calling
+    /// thread. The other scans are not parallel and are not affected.
+    pub fn with_threads(mut self, threads: Option<usize>) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Number of files [`process_directory_queued`](crate::process_directory_queued)
+    /// reads at once, separately from how many it hashes at once, see
+    /// [`with_hash_threads`](Self::with_hash_threads). On network storage many
+    /// concurrent reads hide the latency while little CPU is needed for hashing.
+    /// Setting either one makes reader threads hand file contents in chunks to
+    /// a separate pool of hashers, the unset one defaulting to
+    /// [`with_threads`](Self::with_threads). With neither set each thread reads
+    /// and hashes its own files.
+    pub fn with_io_threads(mut self, threads: Option<usize>) -> Self {
+        self.io_threads = threads;
+        self
+    }
+
+    /// Number of files [`process_directory_queued`](crate::process_directory_queued)
+    /// hashes at once; see [`with_io_threads`](Self::with_io_threads).
+    pub fn with_hash_threads(mut self, threads: Option<usize>) -> Self {
+        self.hash_threads = threads;
+        self
+    }
+
+    pub(crate) fn recorded_algorithm(&self) -> Option<HashAlgorithm> {
+        (self.algorithm != HashAlgorithm::Sha256).then_some(self.algorithm)
+    }
+
+    /// The excluded files as normalized paths relative to `root`.
+    pub(crate) fn excluded_paths(&self, root: &Path) -> HashSet<String> {
+        self.excluded_files
+            .iter()
+            .map(|file| normalize_path(file.strip_prefix(root).unwrap_or(file)))
+            .collect()
+    }
+
+    /// The key an entry path is looked up by when verifying.
+    pub(crate) fn path_key<'p>(&self, path: &'p str) -> Cow<'p, str> {
+        if self.case_insensitive_paths {
+            Cow::Owned(path.to_lowercase())
+        } else {
+            Cow::Borrowed(path)
+        }
+    }
+
+    /// Fails unless the truncation length fits the digests this produces.
+    pub(crate) fn check_truncate(&self) -> KushnResult<()> {
+        let Some(length) = self.truncate else {
+            return Ok(());
+        };
+        let full_length = self
+            .encoding
+            .reencode(Hasher::new(self.algorithm).finalize_hex())
+            .len();
+        if length == 0 || length > full_length {
+            return Err(KushnError::InvalidArgument(format!(
+                "Cannot truncate {} digests to {} characters, they have {}.",
+                self.algorithm, length, full_length
+            )));
+        }
+        Ok(())
+    }
+
+    /// A lowercase hex digest as written to entries: in the chosen encoding,
+    /// truncated if asked to.
+    pub(crate) fn written_hash(&self, hex: String) -> String {
+        let mut hash = self.encoding.reencode(hex);
+        if let Some(length) = self.truncate {
+            hash.truncate(length);
+        }
+        hash
+    }
+}
@@ -0,0 +1,377 @@
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::thread;
+
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+
+use crate::hash::{hash_file_inspecting, hash_reader_inspecting, DEFAULT_BUFFER_SIZE};
+use crate::manifest::sort_entries;
+use crate::options::ProcessOptions;
+use crate::paths::{normalize_path, open_file};
+use crate::scan::{link_bytes, FileError, ScanReport, Scanner, Selected, SelectedFile};
+use crate::{FileHash, KushnError, KushnResult};
+
+/// Paths the walker of [`process_directory_queued`] may queue before it waits
+/// for the workers.
+const QUEUE_CAPACITY: usize = 1024;
+
+/// Chunks a reader of [`process_directory_queued`] may read ahead of the hasher
+/// of its file.
+const CHUNKS_IN_FLIGHT: usize = 16;
+
+fn hash_one(path: &Path, path_string: String, options: &ProcessOptions) -> KushnResult<FileHash> {
+    options.check_truncate()?;
+    let bound_path = options.path_binding.then_some(path_string.as_str());
+    let hash = hash_file_inspecting(path, options.algorithm, bound_path, |_| {})?;
+    let (size, modified) = if options.file_metadata {
+        let metadata = fs::metadata(path)?;
+        (Some(metadata.len()), metadata.modified().ok())
+    } else {
+        (None, None)
+    };
+    Ok(FileHash {
+        algorithm: options.recorded_algorithm(),
+        size,
+        modified,
+        ..FileHash::new(path_string, options.written_hash(hash))
+    })
+}
+
+fn recorded_path(path: &Path) -> String {
+    if path.is_absolute() {
+        path.to_string_lossy().into_owned()
+    } else {
+        normalize_path(path)
+    }
+}
+
+/// Hashes an explicit list of files concurrently, on as many threads as
+/// [`ProcessOptions::with_threads`] asks for. The output has one result per
+/// input path, in input order. Relative paths are recorded as directory scans
+/// record them, so `./src\lib.rs` becomes `src/lib.rs` on Windows; absolute
+/// paths are kept as given.
+pub fn hash_files_parallel(
+    paths: &[PathBuf],
+    options: &ProcessOptions,
+) -> Vec<KushnResult<FileHash>> {
+    let hash_all = || {
+        paths
+            .par_iter()
+            .map(|path| hash_one(path, recorded_path(path), options))
+            .collect()
+    };
+    match options.threads {
+        Some(1) => paths
+            .iter()
+            .map(|path| hash_one(path, recorded_path(path), options))
+            .collect(),
+        // A pool that cannot be started, e.g. for lack of threads, leaves the
+        // work to the global one.
+        Some(threads) => match ThreadPoolBuilder::new().num_threads(threads).build() {
+            Ok(pool) => pool.install(hash_all),
+            Err(_) => hash_all(),
+        },
+        None => hash_all(),
+    }
+}
+
+type Chunk = io::Result<Vec<u8>>;
+
+/// What a reader of [`process_directory_queued`] sends on: the content of a
+/// file, or the target of a symlink hashed as such.
+enum Content {
+    File(File),
+    Link(Vec<u8>),
+}
+
+impl SelectedFile {
+    fn hash(self, options: &ProcessOptions) -> KushnResult<FileHash> {
+        let bound_path = options.path_binding.then_some(self.path_string.as_str());
+        let hash = if self.is_link {
+            let target = link_bytes(&fs::read_link(&self.path)?);
+            hash_reader_inspecting(
+                &mut target.as_slice(),
+                options.algorithm,
+                bound_path,
+                |_| {},
+            )?
+        } else {
+            hash_file_inspecting(&self.path, options.algorithm, bound_path, |_| {})?
+        };
+        Ok(self.into_entry(hash, options))
+    }
+
+    fn into_entry(self, hash: String, options: &ProcessOptions) -> FileHash {
+        FileHash {
+            link_target: self.link_target,
+            algorithm: options.recorded_algorithm(),
+            size: options.file_metadata.then_some(self.metadata.len()),
+            modified: options
+                .file_metadata
+                .then(|| self.metadata.modified().ok())
+                .flatten(),
+            ..FileHash::new(self.entry_path, options.written_hash(hash))
+        }
+    }
+}
+
+/// A file handed from a reader to a hasher of [`process_directory_queued`], its
+/// content following in chunks. A closed channel ends the content.
+struct ReadJob {
+    file: SelectedFile,
+    chunks: Receiver<Chunk>,
+}
+
+impl ReadJob {
+    fn hash(self, options: &ProcessOptions) -> Result<FileHash, FileError> {
+        let bound_path = options
+            .path_binding
+            .then_some(self.file.path_string.as_str());
+        let mut reader = ChunkReader {
+            chunks: self.chunks,
+            chunk: Vec::new(),
+            position: 0,
+        };
+        let hash = match hash_reader_inspecting(&mut reader, options.algorithm, bound_path, |_| {})
+        {
+            Ok(hash) => hash,
+            Err(err) => return Err((self.file.path, err.into())),
+        };
+        Ok(self.file.into_entry(hash, options))
+    }
+}
+
+struct ChunkReader {
+    chunks: Receiver<Chunk>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.chunk.len() {
+            match self.chunks.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.position = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let read = buffer.len().min(self.chunk.len() - self.position);
+        buffer[..read].copy_from_slice(&self.chunk[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
+    }
+}
+
+/// Sends the content of `file` until it ends, fails or the hasher gives up.
+fn read_chunks(mut file: File, chunks: &SyncSender<Chunk>) {
+    loop {
+        let mut chunk = vec![0u8; DEFAULT_BUFFER_SIZE];
+        let sent = match file.read(&mut chunk) {
+            Ok(0) => return,
+            Ok(read) => {
+                chunk.truncate(read);
+                chunks.send(Ok(chunk))
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => chunks.send(Err(err)),
+        };
+        if sent.is_err() {
+            return;
+        }
+    }
+}
+
+fn next_item<T>(receiver: &Mutex<Receiver<T>>) -> Option<T> {
+    receiver.lock().ok()?.recv().ok()
+}
+
+/// Scans a directory on plain threads: one walks the tree and queues the files
+/// to hash, the others hash them. The queue is bounded, so the walker cannot run
+/// far ahead of the workers on huge trees. The number of workers follows
+/// [`ProcessOptions::with_threads`], defaulting to the available parallelism.
+/// With [`ProcessOptions::with_io_threads`] or
+/// [`ProcessOptions::with_hash_threads`] the workers are split into readers
+/// and hashers instead.
+///
+/// Files are selected exactly as by
+/// [`process_directory_with`](crate::process_directory_with): ignore files,
+/// patterns, sampling, age and size filters and the file, directory and size
+/// limits all apply, and empty directories are recorded if asked to. Files are
+/// hashed as they are stored, with the algorithm, encoding, path binding and
+/// file metadata settings, and symlinks follow the
+/// [`ProcessOptions::with_symlinks`] policy. Settings that need to look at the
+/// content, such as decompression, content types or the cache, are not used.
+/// Failures stop the scan unless [`ProcessOptions::with_continue_on_error`] is
+/// set, in which case failed files are left out.
+pub fn process_directory_queued<P: AsRef<Path>>(
+    directory_path: P,
+    options: &ProcessOptions,
+) -> KushnResult<Vec<FileHash>> {
+    scan_directory_queued(directory_path, options).map(|report| report.entries)
+}
+
+/// Scans like [`process_directory_queued`] and also returns the warnings and,
+/// with [`ProcessOptions::with_continue_on_error`], the files that could not be
+/// hashed, as [`scan_directory`](crate::scan_directory) does. Uniform files and
+/// timings are not collected.
+pub fn scan_directory_queued<P: AsRef<Path>>(
+    directory_path: P,
+    options: &ProcessOptions,
+) -> KushnResult<ScanReport> {
+    let root = directory_path.as_ref();
+    let workers = options
+        .threads
+        .or_else(|| thread::available_parallelism().ok().map(NonZeroUsize::get))
+        .unwrap_or(1)
+        .max(1);
+    let stop = AtomicBool::new(false);
+    let (file_sender, file_receiver) = mpsc::sync_channel::<SelectedFile>(QUEUE_CAPACITY);
+    let file_receiver = Mutex::new(file_receiver);
+    let (result_sender, result_receiver) = mpsc::channel::<Result<FileHash, FileError>>();
+    let (job_sender, job_receiver) = mpsc::sync_channel::<ReadJob>(QUEUE_CAPACITY);
+    let job_receiver = Mutex::new(job_receiver);
+
+    let mut entries = Vec::new();
+    let mut failures = Vec::new();
+    let mut failure = None;
+    let walked = thread::scope(|scope| {
+        let walk_results = result_sender.clone();
+        let stop = &stop;
+        // Errors of the walk itself, such as exceeded limits, end the scan
+        // whatever `continue_on_error` says, as they do for the serial scan.
+        let walker = scope.spawn(move || -> KushnResult<ScanReport> {
+            let mut scanner = Scanner::new(root, Cow::Borrowed(options), None, None)?;
+            while let Some(selected) = scanner.next_selected()? {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let sent = match selected {
+                    Selected::Entry(entry) => walk_results.send(Ok(entry)).is_ok(),
+                    // Blocks while the queue is full; dropping the sender at the
+                    // end lets the workers finish once it is drained.
+                    Selected::File(file) => file_sender.send(file).is_ok(),
+                };
+                if !sent {
+                    break;
+                }
+            }
+            Ok(scanner.into_report())
+        });
+
+        if options.io_threads.is_none() && options.hash_threads.is_none() {
+            for _ in 0..workers {
+                let result_sender = result_sender.clone();
+                let file_receiver = &file_receiver;
+                scope.spawn(move || {
+                    while let Some(file) = next_item(file_receiver) {
+                        // After a failure the queue is still drained, so that a
+                        // walker waiting on a full queue gets to see the stop flag.
+                        if stop.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        let path = file.path.clone();
+                        let result = file.hash(options).map_err(|err| (path, err));
+                        if result_sender.send(result).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        } else {
+            for _ in 0..options.io_threads.unwrap_or(workers).max(1) {
+                let job_sender = job_sender.clone();
+                let result_sender = result_sender.clone();
+                let file_receiver = &file_receiver;
+                scope.spawn(move || {
+                    while let Some(file) = next_item(file_receiver) {
+                        if stop.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        let opened = if file.is_link {
+                            fs::read_link(&file.path)
+                                .map(|target| Content::Link(link_bytes(&target)))
+                        } else {
+                            open_file(&file.path).map(Content::File)
+                        };
+                        let opened = match opened {
+                            Ok(opened) => opened,
+                            Err(err) => {
+                                let _ = result_sender.send(Err((file.path, err.into())));
+                                continue;
+                            }
+                        };
+                        let (chunk_sender, chunks) = mpsc::sync_channel(CHUNKS_IN_FLIGHT);
+                        if job_sender.send(ReadJob { file, chunks }).is_err() {
+                            break;
+                        }
+                        match opened {
+                            Content::File(file) => read_chunks(file, &chunk_sender),
+                            Content::Link(target) => {
+                                let _ = chunk_sender.send(Ok(target));
+                            }
+                        }
+                    }
+                });
+            }
+            drop(job_sender);
+            let job_receiver = &job_receiver;
+            for _ in 0..options.hash_threads.unwrap_or(workers).max(1) {
+                let result_sender = result_sender.clone();
+                scope.spawn(move || {
+                    while let Some(job) = next_item(job_receiver) {
+                        // Dropping a job's chunks tells its reader to move on.
+                        if stop.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        if result_sender.send(job.hash(options)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        }
+        drop(result_sender);
+
+        for result in result_receiver {
+            match result {
+                Ok(entry) => entries.push(entry),
+                Err((path, err)) if options.continue_on_error => failures.push((path, err)),
+                Err((path, err)) => {
+                    stop.store(true, Ordering::Relaxed);
+                    failure.get_or_insert(err.with_path(path));
+                }
+            }
+        }
+        walker
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    });
+
+    let mut report = walked?;
+    if let Some(err) = failure {
+        return Err(err);
+    }
+    for (path, err) in failures {
+        report.record_failure(path, err, options)?;
+    }
+    if options.sorted {
+        sort_entries(&mut entries);
+    }
+    if !options.allow_empty && entries.is_empty() {
+        return Err(KushnError::NoFilesFound {
+            path: root.to_path_buf(),
+        });
+    }
+    report.entries = entries;
+    Ok(report)
+}
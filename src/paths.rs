@@ -0,0 +1,65 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path};
+
+/// Renders a relative path the way it is stored in manifests: components joined
+/// with `/` whatever the platform separator is, and without `.` components.
+pub fn normalize_path(path: &Path) -> String {
+    let mut normalized = String::with_capacity(path.as_os_str().len());
+    for component in path.components() {
+        let segment = match component {
+            Component::Normal(segment) => segment.to_string_lossy(),
+            Component::ParentDir => "..".into(),
+            _ => continue,
+        };
+        if !normalized.is_empty() {
+            normalized.push('/');
+        }
+        normalized.push_str(&segment);
+    }
+    normalized
+}
+
+pub const WINDOWS_MAX_PATH: usize = 260;
+
+/// Rewrites a long absolute Windows path into its extended-length `\\?\` form,
+/// which lifts the `MAX_PATH` limit. The prefix disables Win32 path parsing, so
+/// forward slashes are converted to backslashes. Short, relative and already
+/// prefixed paths are returned unchanged.
+pub fn to_extended_length_path(path: &str) -> String {
+    if path.len() < WINDOWS_MAX_PATH || path.starts_with(r"\\?\") {
+        return path.to_owned();
+    }
+
+    let is_separator = |byte: u8| byte == b'\\' || byte == b'/';
+    let bytes = path.as_bytes();
+    if is_separator(bytes[0]) && is_separator(bytes[1]) {
+        return format!(r"\\?\UNC\{}", path[2..].replace('/', "\\"));
+    }
+    let is_drive_absolute =
+        bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && is_separator(bytes[2]);
+    if is_drive_absolute {
+        format!(r"\\?\{}", path.replace('/', "\\"))
+    } else {
+        path.to_owned()
+    }
+}
+
+#[cfg(windows)]
+pub(crate) fn open_file<P: AsRef<Path>>(path: P) -> io::Result<fs::File> {
+    let path = path.as_ref();
+    // A short relative path can still exceed the limit once it is resolved
+    // against the working directory, so the resolved length is what counts.
+    let absolute = std::path::absolute(path)?;
+    match absolute.to_str() {
+        Some(absolute) if absolute.len() >= WINDOWS_MAX_PATH => {
+            fs::File::open(to_extended_length_path(absolute))
+        }
+        _ => fs::File::open(path),
+    }
+}
+
+#[cfg(not(windows))]
+pub(crate) fn open_file<P: AsRef<Path>>(path: P) -> io::Result<fs::File> {
+    fs::File::open(path)
+}
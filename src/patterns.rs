@@ -0,0 +1,338 @@
+use std::fs;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern, PatternError};
+
+use crate::paths::normalize_path;
+use crate::{KushnError, KushnResult};
+
+pub const INCLUDE_DIRECTIVE: &str = "include ";
+
+/// Name of the ignore file read from the scanned directory and, with
+/// [`ProcessOptions::with_nested_ignore`](crate::ProcessOptions::with_nested_ignore),
+/// from each of its subdirectories.
+pub const IGNORE_FILE_NAME: &str = ".kushnignore";
+
+/// Marks a comment line in ignore files, as in `.gitignore`.
+pub const COMMENT_PREFIX: char = '#';
+
+/// Splits the contents of an ignore file into its lines, trimmed, leaving out
+/// blank lines and comments. `include` lines are returned as they are.
+pub fn parse_ignore_file(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(COMMENT_PREFIX))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Reads the patterns of an ignore file, as split by [`parse_ignore_file`]. A
+/// line of the form `include <path>` is replaced by the patterns of that file,
+/// resolved relative to the including file, so several projects can share a base
+/// ignore file. Includes that lead back to a file currently being read fail with
+/// [`KushnError::IgnoreIncludeCycle`].
+pub fn load_ignore_file<P: AsRef<Path>>(path: P) -> KushnResult<Vec<String>> {
+    let mut patterns = Vec::new();
+    load_ignore_file_into(path.as_ref(), &mut Vec::new(), &mut patterns)?;
+    Ok(patterns)
+}
+
+fn load_ignore_file_into(
+    path: &Path,
+    including: &mut Vec<PathBuf>,
+    patterns: &mut Vec<String>,
+) -> KushnResult<()> {
+    let canonical = fs::canonicalize(path)?;
+    if including.contains(&canonical) {
+        return Err(KushnError::IgnoreIncludeCycle {
+            path: path.to_path_buf(),
+        });
+    }
+    let contents = fs::read_to_string(&canonical)?;
+    including.push(canonical);
+
+    for line in parse_ignore_file(&contents) {
+        match line.strip_prefix(INCLUDE_DIRECTIVE) {
+            Some(included) => {
+                let base = path.parent().unwrap_or(Path::new(""));
+                load_ignore_file_into(&base.join(included.trim()), including, patterns)?;
+            }
+            None => patterns.push(line),
+        }
+    }
+
+    including.pop();
+    Ok(())
+}
+
+/// Ignore patterns may be written with either separator; they are matched
+/// against paths rendered by [`normalize_path`], so both sides use `/`.
+pub fn normalize_pattern(pattern: &str) -> String {
+    pattern.replace('\\', "/")
+}
+
+pub const NEGATION_PREFIX: char = '!';
+
+/// Splits the gitignore-style markers off a pattern: a leading `!` re-includes
+/// what earlier patterns excluded, a trailing `/` restricts it to directories.
+fn parse_pattern(pattern: &str) -> (bool, bool, String) {
+    let pattern = normalize_pattern(pattern);
+    let (negated, pattern) = match pattern.strip_prefix(NEGATION_PREFIX) {
+        Some(pattern) => (true, pattern),
+        None => (false, pattern.as_str()),
+    };
+    let (dir_only, pattern) = match pattern.strip_suffix('/') {
+        Some(pattern) if !pattern.is_empty() => (true, pattern),
+        _ => (false, pattern),
+    };
+    (negated, dir_only, pattern.to_owned())
+}
+
+/// Builds the file half of each pattern, matching at any depth. `!` and a
+/// trailing `/` are stripped; their meaning is applied by the scans, see
+/// [`ProcessOptions::with_ignore`](crate::ProcessOptions::with_ignore).
+pub fn build_file_ignore_patterns(ignore: &[String]) -> Result<Vec<Pattern>, PatternError> {
+    ignore
+        .iter()
+        .map(|pattern| Pattern::new(&format!("**/{}", parse_pattern(pattern).2)))
+        .collect()
+}
+
+/// Builds the directory half of each pattern, matching relative to the root.
+/// Markers are stripped as by [`build_file_ignore_patterns`].
+pub fn build_dir_ignore_patterns(ignore: &[String]) -> Result<Vec<Pattern>, PatternError> {
+    ignore
+        .iter()
+        .map(|pattern| Pattern::new(&parse_pattern(pattern).2))
+        .collect()
+}
+
+fn match_options(case_sensitive: bool) -> MatchOptions {
+    MatchOptions {
+        case_sensitive,
+        ..MatchOptions::new()
+    }
+}
+
+fn matches(pattern: &Pattern, path: &str, options: MatchOptions) -> bool {
+    pattern.matches_with(path, options)
+}
+
+/// Directories above `path`, shortest first.
+fn ancestors(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/')
+        .map(move |(index, _)| &path[..index])
+}
+
+struct IgnoreRule {
+    negated: bool,
+    dir_only: bool,
+    file_pattern: Pattern,
+    dir_pattern: Pattern,
+}
+
+impl IgnoreRule {
+    /// Whether the rule names `path` itself, not counting its parents.
+    fn matches_directly(&self, path: &str, is_dir: bool, options: MatchOptions) -> bool {
+        if is_dir {
+            matches(&self.dir_pattern, path, options)
+        } else {
+            !self.dir_only && matches(&self.file_pattern, path, options)
+        }
+    }
+}
+
+pub(crate) struct IgnoreMatcher {
+    rules: Vec<IgnoreRule>,
+    has_negations: bool,
+    match_options: MatchOptions,
+}
+
+impl IgnoreMatcher {
+    pub(crate) fn new(ignore: &[String], case_sensitive: bool) -> Result<Self, PatternError> {
+        let rules = ignore
+            .iter()
+            .map(|pattern| {
+                let (negated, dir_only, pattern) = parse_pattern(pattern);
+                Ok(IgnoreRule {
+                    negated,
+                    dir_only,
+                    file_pattern: Pattern::new(&format!("**/{}", pattern))?,
+                    dir_pattern: Pattern::new(&pattern)?,
+                })
+            })
+            .collect::<Result<Vec<_>, PatternError>>()?;
+        Ok(IgnoreMatcher {
+            has_negations: rules.iter().any(|rule| rule.negated),
+            rules,
+            match_options: match_options(case_sensitive),
+        })
+    }
+
+    /// Evaluates the rules in order, the last one matching `path` or one of its
+    /// parent directories deciding. Parents only need checking when they may be
+    /// ignored without having been pruned from the walk.
+    fn is_ignored(&self, path: &str, is_dir: bool, check_ancestors: bool) -> bool {
+        self.apply(path, is_dir, check_ancestors, false)
+    }
+
+    /// Like [`is_ignored`](Self::is_ignored), starting from the state `ignored`
+    /// left by the rules of enclosing ignore files.
+    fn apply(&self, path: &str, is_dir: bool, check_ancestors: bool, mut ignored: bool) -> bool {
+        for rule in &self.rules {
+            // Only a rule that would flip the current state can change the outcome.
+            if rule.negated != ignored {
+                continue;
+            }
+            let matched = rule.matches_directly(path, is_dir, self.match_options)
+                || (check_ancestors
+                    && ancestors(path)
+                        .any(|ancestor| matches(&rule.dir_pattern, ancestor, self.match_options)));
+            if matched {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+
+    /// Whether a negation could re-include something below the directory `path`.
+    /// File negations match at any depth, so only directory negations such as
+    /// `!target/keep/` can be ruled out.
+    fn may_reinclude_below(&self, path: &str) -> bool {
+        self.rules.iter().filter(|rule| rule.negated).any(|rule| {
+            if !rule.dir_only {
+                return true;
+            }
+            let pattern = rule.dir_pattern.as_str();
+            let pattern_components: Vec<&str> = pattern.split('/').collect();
+            let mut path_components = path.split('/');
+            for pattern_component in &pattern_components {
+                match path_components.next() {
+                    None => return true,
+                    Some(_) if *pattern_component == "**" => return true,
+                    Some(component) => match Pattern::new(pattern_component) {
+                        Ok(pattern) if matches(&pattern, component, self.match_options) => {}
+                        _ => return false,
+                    },
+                }
+            }
+            false
+        })
+    }
+
+    /// Whether the walk can skip the directory and everything below it.
+    pub(crate) fn is_dir_ignored(&self, relative_path: &Path) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        let path = normalize_path(relative_path);
+        self.is_ignored(&path, true, self.has_negations) && !self.may_reinclude_below(&path)
+    }
+
+    /// Checks a file reached through a walk that pruned the directories
+    /// [`is_dir_ignored`](Self::is_dir_ignored) rejected, for a path already
+    /// rendered by [`normalize_path`].
+    pub(crate) fn is_normalized_file_ignored(&self, normalized_path: &str) -> bool {
+        !self.rules.is_empty() && self.is_ignored(normalized_path, false, self.has_negations)
+    }
+
+    /// Checks a file path together with all of its parent directories, for paths
+    /// that were not reached through a walk which already pruned ignored directories.
+    pub(crate) fn is_path_ignored(&self, relative_path: &Path) -> bool {
+        !self.rules.is_empty() && self.is_ignored(&normalize_path(relative_path), false, true)
+    }
+}
+
+/// Allowlist of file patterns, built like [`build_file_ignore_patterns`] so that
+/// `*.rs` matches at any depth. Without patterns everything is included.
+pub(crate) struct IncludeMatcher {
+    patterns: Vec<Pattern>,
+    match_options: MatchOptions,
+}
+
+impl IncludeMatcher {
+    pub(crate) fn new(include: &[String], case_sensitive: bool) -> Result<Self, PatternError> {
+        Ok(IncludeMatcher {
+            patterns: build_file_ignore_patterns(include)?,
+            match_options: match_options(case_sensitive),
+        })
+    }
+
+    pub(crate) fn is_normalized_file_included(&self, normalized_path: &str) -> bool {
+        self.patterns.is_empty()
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| matches(pattern, normalized_path, self.match_options))
+    }
+}
+
+/// The matcher of the scan's own patterns, plus one per ignore file found in a
+/// subdirectory during the walk. A nested file's patterns are relative to its
+/// directory, apply only below it and take precedence over those of enclosing
+/// directories, as with `.gitignore` files.
+pub(crate) struct ScopedIgnore {
+    root: IgnoreMatcher,
+    nested: Vec<(String, IgnoreMatcher)>,
+}
+
+impl ScopedIgnore {
+    pub(crate) fn new(root: IgnoreMatcher) -> Self {
+        ScopedIgnore {
+            root,
+            nested: Vec::new(),
+        }
+    }
+
+    /// Adds the patterns of the ignore file in `directory`, a normalized path
+    /// relative to the root. Directories must be added before anything below
+    /// them is checked, which walks guarantee by yielding a directory before
+    /// reading it.
+    pub(crate) fn add(&mut self, directory: String, matcher: IgnoreMatcher) {
+        if !matcher.rules.is_empty() {
+            self.nested.push((directory, matcher));
+        }
+    }
+
+    /// The matchers applying to `path`, outermost first, each with the path
+    /// relative to its directory.
+    fn scopes<'a>(&'a self, path: &'a str) -> impl Iterator<Item = (&'a IgnoreMatcher, &'a str)> {
+        let nested = self.nested.iter().filter_map(move |(directory, matcher)| {
+            let relative = path.strip_prefix(directory.as_str())?.strip_prefix('/')?;
+            Some((matcher, relative))
+        });
+        iter::once((&self.root, path)).chain(nested)
+    }
+
+    fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        let check_ancestors =
+            self.root.has_negations || self.nested.iter().any(|(_, matcher)| matcher.has_negations);
+        self.scopes(path)
+            .fold(false, |ignored, (matcher, relative)| {
+                matcher.apply(relative, is_dir, check_ancestors, ignored)
+            })
+    }
+
+    /// Same as [`IgnoreMatcher::is_dir_ignored`], for all applying ignore files.
+    pub(crate) fn is_dir_ignored(&self, relative_path: &Path) -> bool {
+        if self.root.rules.is_empty() && self.nested.is_empty() {
+            return false;
+        }
+        let path = normalize_path(relative_path);
+        self.is_ignored(&path, true)
+            && !self
+                .scopes(&path)
+                .any(|(matcher, relative)| matcher.may_reinclude_below(relative))
+    }
+
+    /// Same as [`IgnoreMatcher::is_normalized_file_ignored`], for all applying
+    /// ignore files.
+    pub(crate) fn is_normalized_file_ignored(&self, normalized_path: &str) -> bool {
+        if self.root.rules.is_empty() && self.nested.is_empty() {
+            return false;
+        }
+        self.is_ignored(normalized_path, false)
+    }
+}
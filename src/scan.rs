@@ -0,0 +1,980 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::{self, Metadata};
+use std::io::{Read, Seek, SeekFrom};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use flate2::read::MultiGzDecoder;
+use sha2::{Digest, Sha256};
+
+use crate::cache::CacheKey;
+use crate::content::ContentSniffer;
+use crate::hash::{
+    calculate_file_hash_with, calculate_hash_reader, hash_file_mapped, hash_reader_inspecting,
+    HashAlgorithm, UniformByteCheck,
+};
+use crate::manifest::sort_entries;
+use crate::options::{BaseStripping, ProcessOptions, SymlinkPolicy, MMAP_MIN_SIZE};
+use crate::paths::{normalize_path, open_file};
+use crate::patterns::{load_ignore_file, IgnoreMatcher, IncludeMatcher, ScopedIgnore};
+use crate::walk::{walk, WalkItem};
+use crate::warning::Warning;
+use crate::{FileHash, KushnError, KushnResult};
+
+/// A file that could not be hashed, and why.
+pub type FileError = (PathBuf, KushnError);
+
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub entries: Vec<FileHash>,
+    pub errors: Vec<FileError>,
+    pub uniform_files: Vec<String>,
+    pub warnings: Vec<Warning>,
+    pub timings: PhaseTimings,
+    /// Set when a cancellable scan was stopped early; `entries` then only
+    /// covers the files hashed before that.
+    pub interrupted: bool,
+}
+
+/// Wall-clock time a scan spent in each phase. `walking` covers listing
+/// directories, `metadata` the per-file stat and `hashing` opening and reading
+/// files. `total` also includes filtering and bookkeeping, so the phases add up
+/// to slightly less than it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PhaseTimings {
+    pub walking: Duration,
+    pub metadata: Duration,
+    pub hashing: Duration,
+    pub total: Duration,
+}
+
+impl ScanReport {
+    pub(crate) fn record_failure(
+        &mut self,
+        path: PathBuf,
+        err: KushnError,
+        options: &ProcessOptions,
+    ) -> KushnResult<()> {
+        if !options.continue_on_error {
+            return Err(err);
+        }
+        match Warning::from_error(&path, &err).filter(|_| !options.failures_as_errors) {
+            Some(warning) => self.warnings.push(warning),
+            None => self.errors.push((path, err)),
+        }
+        Ok(())
+    }
+}
+
+fn is_unstable(before: &Metadata, file: &fs::File) -> bool {
+    match file.metadata() {
+        Ok(after) => before.len() != after.len() || before.modified().ok() != after.modified().ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(unix)]
+fn ownership(metadata: &Metadata) -> (Option<u32>, Option<u32>) {
+    use std::os::unix::fs::MetadataExt;
+    (Some(metadata.uid()), Some(metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn ownership(_metadata: &Metadata) -> (Option<u32>, Option<u32>) {
+    (None, None)
+}
+
+#[cfg(unix)]
+pub(crate) fn link_bytes(target: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    target.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn link_bytes(target: &Path) -> Vec<u8> {
+    target.to_string_lossy().into_owned().into_bytes()
+}
+
+fn inspect_chunk(
+    chunk: &[u8],
+    options: &ProcessOptions,
+    uniform_check: &mut UniformByteCheck,
+    sniffer: &mut ContentSniffer,
+) {
+    if options.detect_uniform {
+        uniform_check.update(chunk);
+    }
+    if options.content_type {
+        sniffer.update(chunk);
+    }
+}
+
+fn dedup_by_content(entries: &mut Vec<FileHash>) {
+    let mut kept: HashMap<&str, usize> = HashMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        if entry.is_dir {
+            continue;
+        }
+        kept.entry(entry.hash.as_str())
+            .and_modify(|kept_index| {
+                if entry.path < entries[*kept_index].path {
+                    *kept_index = index;
+                }
+            })
+            .or_insert(index);
+    }
+    let kept: HashSet<usize> = kept.into_values().collect();
+    let mut index = 0;
+    entries.retain(|entry| {
+        index += 1;
+        entry.is_dir || kept.contains(&(index - 1))
+    });
+}
+
+/// What entry paths are prefixed with: the scan root's path below the base
+/// directory, or its own name for [`BaseStripping::Inclusive`].
+fn root_prefix(directory_path: &Path, options: &ProcessOptions) -> KushnResult<Option<String>> {
+    let prefix = match (&options.base_dir, options.base_stripping) {
+        (Some(base_dir), _) => {
+            let root = fs::canonicalize(directory_path)?;
+            let base = fs::canonicalize(base_dir)?;
+            let relative = root
+                .strip_prefix(&base)
+                .map_err(|_| KushnError::OutsideBaseDir {
+                    path: directory_path.to_path_buf(),
+                    base: base_dir.clone(),
+                })?;
+            Some(normalize_path(relative))
+        }
+        (None, BaseStripping::Exclusive) => None,
+        (None, BaseStripping::Inclusive) => fs::canonicalize(directory_path)?
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned()),
+    };
+    Ok(prefix.filter(|prefix| !prefix.is_empty()))
+}
+
+pub(crate) fn is_sampled(relative_path: &str, fraction: f64, seed: u64) -> bool {
+    let mut hasher = Sha256::new();
+    hasher.update(seed.to_le_bytes());
+    hasher.update(relative_path.as_bytes());
+    let digest = hasher.finalize();
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(prefix) as f64 / u64::MAX as f64) < fraction
+}
+
+pub fn process_file<P: AsRef<Path>>(
+    file_path: P,
+    ignore: &[String],
+) -> KushnResult<Option<FileHash>> {
+    process_file_with(
+        file_path,
+        &ProcessOptions::new().with_ignore(ignore.to_vec()),
+    )
+}
+
+/// Same as [`process_file`], honouring the ignore patterns, algorithm, encoding
+/// and file metadata settings of `options`.
+pub fn process_file_with<P: AsRef<Path>>(
+    file_path: P,
+    options: &ProcessOptions,
+) -> KushnResult<Option<FileHash>> {
+    let file_path = file_path.as_ref();
+    let base_dir = match &options.base_dir {
+        Some(base_dir) => base_dir.clone(),
+        None => env::current_dir()?,
+    };
+    let relative_path =
+        file_path
+            .strip_prefix(&base_dir)
+            .map_err(|_| KushnError::OutsideBaseDir {
+                path: file_path.to_path_buf(),
+                base: base_dir.clone(),
+            })?;
+
+    if IgnoreMatcher::new(&options.ignore, !options.case_insensitive)?
+        .is_path_ignored(relative_path)
+    {
+        return Ok(None);
+    }
+
+    options.check_truncate()?;
+    let hash = options.written_hash(calculate_file_hash_with(file_path, options.algorithm)?);
+    let (size, modified) = if options.file_metadata {
+        let metadata = fs::metadata(file_path)?;
+        (Some(metadata.len()), metadata.modified().ok())
+    } else {
+        (None, None)
+    };
+    let path_string = normalize_path(relative_path);
+    Ok(Some(FileHash {
+        algorithm: options.recorded_algorithm(),
+        size,
+        modified,
+        ..FileHash::new(path_string, hash)
+    }))
+}
+
+/// Hashes everything read from `reader` into an entry recorded under
+/// `logical_path`, e.g. for content generated by a pipeline that never lands on disk.
+pub fn process_reader<R: Read>(
+    reader: &mut R,
+    logical_path: &str,
+    algorithm: HashAlgorithm,
+) -> KushnResult<FileHash> {
+    let hash = calculate_hash_reader(reader, algorithm)?;
+    Ok(FileHash {
+        algorithm: (algorithm != HashAlgorithm::Sha256).then_some(algorithm),
+        ..FileHash::new(normalize_path(Path::new(logical_path)), hash)
+    })
+}
+
+pub fn scan_directory<P: AsRef<Path>>(
+    directory_path: P,
+    options: &ProcessOptions,
+) -> KushnResult<ScanReport> {
+    scan_directory_streaming(directory_path, options, |_| {})
+}
+
+/// Same as [`scan_directory`], additionally handing each entry to `on_entry` as
+/// soon as it is hashed, for live output while the scan is running.
+pub fn scan_directory_streaming<P, F>(
+    directory_path: P,
+    options: &ProcessOptions,
+    on_entry: F,
+) -> KushnResult<ScanReport>
+where
+    P: AsRef<Path>,
+    F: FnMut(&FileHash),
+{
+    scan(directory_path.as_ref(), options, None, on_entry, None, None)
+}
+
+/// Scans like [`scan_directory`] until `cancel` is set, e.g. from a signal
+/// handler, then stops and returns what was hashed so far with
+/// [`ScanReport::interrupted`] set.
+pub fn process_directory_cancellable<P: AsRef<Path>>(
+    directory_path: P,
+    options: &ProcessOptions,
+    cancel: &AtomicBool,
+) -> KushnResult<ScanReport> {
+    scan(
+        directory_path.as_ref(),
+        options,
+        Some(cancel),
+        |_| {},
+        None,
+        None,
+    )
+}
+
+/// Scans like [`scan_directory`], calling `progress` after each file, hashed or
+/// failed, with its path, the number of files processed so far and the total.
+/// The total is only known, and otherwise 0, with
+/// [`ProcessOptions::with_count_first`]. `cancel` stops the scan like
+/// [`process_directory_cancellable`].
+pub fn process_directory_with_progress<P, F>(
+    directory_path: P,
+    options: &ProcessOptions,
+    cancel: Option<&AtomicBool>,
+    mut progress: F,
+) -> KushnResult<ScanReport>
+where
+    P: AsRef<Path>,
+    F: FnMut(&Path, usize, usize),
+{
+    scan(
+        directory_path.as_ref(),
+        options,
+        cancel,
+        |_| {},
+        Some(&mut progress),
+        None,
+    )
+}
+
+/// Scans like [`process_directory_with`], only hashing the files for which
+/// `filter`, given the path on disk and the metadata, returns `true`, for rules
+/// that patterns cannot express such as the owner of a file. The walk, ignore
+/// and include patterns, excluded files, sampling, the maximum age and the size
+/// range are applied first, so `filter` only sees files that would otherwise be
+/// hashed. Limits such as [`ProcessOptions::with_max_file_size`] are checked
+/// after it, on the files it keeps.
+pub fn process_directory_filtered<P, F>(
+    directory_path: P,
+    options: &ProcessOptions,
+    filter: F,
+) -> KushnResult<Vec<FileHash>>
+where
+    P: AsRef<Path>,
+    F: Fn(&Path, &Metadata) -> bool,
+{
+    scan(
+        directory_path.as_ref(),
+        options,
+        None,
+        |_| {},
+        None,
+        Some(&filter),
+    )
+    .map(|report| report.entries)
+}
+
+/// Number of files a scan with `options` would consider: those not ignored and
+/// not skipped as symlinks or special files. Filters that need each file's
+/// metadata, such as sampling or a maximum age, are not applied, so the scan
+/// may hash fewer.
+pub fn count_files<P: AsRef<Path>>(
+    directory_path: P,
+    options: &ProcessOptions,
+) -> KushnResult<usize> {
+    let root = directory_path.as_ref();
+    let ignore = IgnoreMatcher::new(&options.ignore, !options.case_insensitive)?;
+    let include = IncludeMatcher::new(&options.include, !options.case_insensitive)?;
+    let excluded = options.excluded_paths(root);
+    let walker = walk(root, options, |path| {
+        ignore.is_dir_ignored(path.strip_prefix(root).unwrap_or(path))
+    });
+    let count = walker
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let file_type = entry.file_type();
+            let is_link = file_type.is_symlink();
+            if file_type.is_dir()
+                || (is_link && options.symlinks != SymlinkPolicy::HashLinkTarget)
+                || (options.skip_special_files && !file_type.is_file() && !is_link)
+            {
+                return false;
+            }
+            let path = entry.path();
+            let path_string = normalize_path(path.strip_prefix(root).unwrap_or(path));
+            !ignore.is_normalized_file_ignored(&path_string)
+                && include.is_normalized_file_included(&path_string)
+                && !excluded.contains(&path_string)
+        })
+        .count();
+    Ok(count)
+}
+
+/// Paths a scan with `options` would hash, without reading any file: the same
+/// walk, ignore and include patterns, filters and limits apply, so this shows
+/// what a pattern matches before a large tree is hashed. Unreadable entries end
+/// the listing unless [`ProcessOptions::with_continue_on_error`] is set.
+pub fn list_files<P: AsRef<Path>>(
+    directory_path: P,
+    options: &ProcessOptions,
+) -> KushnResult<Vec<String>> {
+    let mut scanner = Scanner::new(directory_path.as_ref(), Cow::Borrowed(options), None, None)?;
+    scanner.list_only = true;
+    let mut paths = Vec::new();
+    while let Some(entry) = scanner.next_entry()? {
+        paths.push(entry.path);
+    }
+    if options.sorted {
+        paths.sort();
+    }
+    Ok(paths)
+}
+
+/// Yields the entries of a directory one by one while the walk proceeds, so
+/// huge trees can be written out without holding every entry in memory. They
+/// come in walk order, not sorted like the collecting scans return them. A file
+/// that fails to hash yields an `Err` and the walk moves on; warnings such as
+/// skipped symlinks are not reported.
+pub fn process_directory_iter<P: AsRef<Path>>(
+    directory_path: P,
+    ignore: &[String],
+) -> impl Iterator<Item = KushnResult<FileHash>> {
+    let options = ProcessOptions::new().with_ignore(ignore.to_vec());
+    let mut scanner = Some(Scanner::new(
+        directory_path.as_ref(),
+        Cow::Owned(options),
+        None,
+        None,
+    ));
+    iter::from_fn(move || match scanner.as_mut()? {
+        Ok(scanner) => scanner.next_entry().transpose(),
+        Err(_) => scanner.take().and_then(Result::err).map(Err),
+    })
+}
+
+type ProgressFn<'o> = dyn FnMut(&Path, usize, usize) + 'o;
+type FilterFn<'o> = dyn Fn(&Path, &Metadata) -> bool + 'o;
+
+/// The scan loop, producing one entry per call so that both the collecting
+/// scans and [`process_directory_iter`] can drive it.
+pub(crate) struct Scanner<'o> {
+    options: Cow<'o, ProcessOptions>,
+    root: PathBuf,
+    ignore: Rc<RefCell<ScopedIgnore>>,
+    include: IncludeMatcher,
+    excluded: HashSet<String>,
+    walker: Box<dyn Iterator<Item = WalkItem> + 'o>,
+    root_name: Option<String>,
+    cancel: Option<&'o AtomicBool>,
+    progress: Option<&'o mut ProgressFn<'o>>,
+    /// Set by `process_directory_filtered`.
+    filter: Option<&'o FilterFn<'o>>,
+    total: usize,
+    scan_started: SystemTime,
+    dir_count: usize,
+    hashed: usize,
+    processed: usize,
+    /// Yields entries without hashes instead of reading the files.
+    list_only: bool,
+    /// The file behind the last entry yielded in `list_only` mode.
+    selected: Option<SelectedFile>,
+    /// Entries of an earlier manifest by path, whose hashes are reused for
+    /// files with the same size and mtime.
+    previous: HashMap<&'o str, &'o FileHash>,
+    /// Everything but the entries, which are handed out by `next_entry`.
+    report: ScanReport,
+}
+
+/// A file that passed every filter of the scan, for
+/// [`process_directory_queued`](crate::process_directory_queued) to hash
+/// elsewhere.
+pub(crate) struct SelectedFile {
+    pub(crate) path: PathBuf,
+    /// Relative to the root, as path binding hashes it.
+    pub(crate) path_string: String,
+    /// As recorded in the entry.
+    pub(crate) entry_path: String,
+    pub(crate) metadata: Metadata,
+    pub(crate) is_link: bool,
+    pub(crate) link_target: Option<String>,
+}
+
+/// What [`Scanner::next_selected`] yields.
+pub(crate) enum Selected {
+    /// An entry that needs no hashing, such as an empty directory.
+    Entry(FileHash),
+    File(SelectedFile),
+}
+
+impl<'o> Scanner<'o> {
+    pub(crate) fn new(
+        directory_path: &Path,
+        options: Cow<'o, ProcessOptions>,
+        cancel: Option<&'o AtomicBool>,
+        progress: Option<&'o mut ProgressFn<'o>>,
+    ) -> KushnResult<Self> {
+        options.check_truncate()?;
+        let total = if progress.is_some() && options.count_first {
+            count_files(directory_path, &options)?
+        } else {
+            0
+        };
+        let ignore = Rc::new(RefCell::new(ScopedIgnore::new(IgnoreMatcher::new(
+            &options.ignore,
+            !options.case_insensitive,
+        )?)));
+        let root = directory_path.to_path_buf();
+        let root_name = root_prefix(directory_path, &options)?;
+        let walker = {
+            let ignore = Rc::clone(&ignore);
+            let root = root.clone();
+            walk(directory_path, &options, move |path| {
+                let relative_path = path.strip_prefix(&root).unwrap_or(path);
+                ignore.borrow().is_dir_ignored(relative_path)
+            })
+        };
+        Ok(Scanner {
+            include: IncludeMatcher::new(&options.include, !options.case_insensitive)?,
+            excluded: options.excluded_paths(directory_path),
+            options,
+            root,
+            ignore,
+            walker,
+            root_name,
+            cancel,
+            progress,
+            filter: None,
+            total,
+            scan_started: SystemTime::now(),
+            dir_count: 0,
+            hashed: 0,
+            processed: 0,
+            list_only: false,
+            selected: None,
+            previous: HashMap::new(),
+            report: ScanReport::default(),
+        })
+    }
+
+    /// Hashes files until one produces an entry. `Ok(None)` ends the scan.
+    fn next_entry(&mut self) -> KushnResult<Option<FileHash>> {
+        let options = &*self.options;
+        loop {
+            if self
+                .cancel
+                .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+            {
+                self.report.interrupted = true;
+                return Ok(None);
+            }
+            let walk_started = Instant::now();
+            let entry = self.walker.next();
+            self.report.timings.walking += walk_started.elapsed();
+            let Some(entry) = entry else {
+                return Ok(None);
+            };
+
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err((path, err)) => {
+                    self.report.record_failure(path, err, options)?;
+                    continue;
+                }
+            };
+
+            let file_type = entry.file_type();
+            if file_type.is_dir() {
+                self.dir_count += 1;
+                if let Some(limit) = options.max_dirs {
+                    if self.dir_count > limit {
+                        return Err(KushnError::DirLimitExceeded { limit });
+                    }
+                }
+                let is_empty_dir = options.empty_dirs
+                    && entry.path() != self.root
+                    && fs::read_dir(entry.path()).is_ok_and(|mut entries| entries.next().is_none());
+                if is_empty_dir {
+                    let relative_path = entry.path().strip_prefix(&self.root);
+                    let path_string = normalize_path(relative_path.unwrap_or(entry.path()));
+                    return Ok(Some(FileHash {
+                        is_dir: true,
+                        ..FileHash::new(self.entry_path(path_string), String::new())
+                    }));
+                }
+                let ignore_file = entry.path().join(&options.ignore_file_name);
+                if !options.nested_ignore || entry.path() == self.root || !ignore_file.is_file() {
+                    continue;
+                }
+                // Read before the walk lists the directory's contents.
+                let matcher = load_ignore_file(&ignore_file).and_then(|patterns| {
+                    Ok(IgnoreMatcher::new(&patterns, !options.case_insensitive)?)
+                });
+                match matcher {
+                    Ok(matcher) => {
+                        let relative_path = entry.path().strip_prefix(&self.root);
+                        self.ignore.borrow_mut().add(
+                            normalize_path(relative_path.unwrap_or(entry.path())),
+                            matcher,
+                        );
+                    }
+                    Err(err) => self.report.record_failure(ignore_file, err, options)?,
+                }
+                continue;
+            }
+            let is_link = file_type.is_symlink();
+            if is_link && options.symlinks != SymlinkPolicy::HashLinkTarget {
+                self.report
+                    .warnings
+                    .push(Warning::SkippedSymlink(entry.path().to_path_buf()));
+                continue;
+            }
+            if options.skip_special_files && !file_type.is_file() && !is_link {
+                self.report
+                    .warnings
+                    .push(Warning::SkippedSpecialFile(entry.path().to_path_buf()));
+                continue;
+            }
+
+            let path = entry.path();
+            let relative_path = path.strip_prefix(&self.root).unwrap_or(path);
+            let path_string = normalize_path(relative_path);
+            if path_string.is_empty() && self.root_name.is_none() {
+                continue;
+            }
+            if self
+                .ignore
+                .borrow()
+                .is_normalized_file_ignored(&path_string)
+                || !self.include.is_normalized_file_included(&path_string)
+                || self.excluded.contains(&path_string)
+            {
+                continue;
+            }
+
+            if let Some((fraction, seed)) = options.sample {
+                if !is_sampled(&path_string, fraction, seed) {
+                    continue;
+                }
+            }
+
+            // One stat per file, shared by the filters, the cache key and the
+            // stability check below.
+            let metadata_started = Instant::now();
+            let metadata = entry.metadata();
+            self.report.timings.metadata += metadata_started.elapsed();
+            let metadata = match metadata {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    self.report
+                        .record_failure(path.to_path_buf(), err.into(), options)?;
+                    continue;
+                }
+            };
+
+            if let Some(max_age) = options.max_age {
+                if let Ok(modified) = metadata.modified() {
+                    let age = self
+                        .scan_started
+                        .duration_since(modified)
+                        .unwrap_or_default();
+                    if age > max_age {
+                        continue;
+                    }
+                }
+            }
+
+            let size = metadata.len();
+            if options.min_size.is_some_and(|min_size| size < min_size)
+                || options.max_size.is_some_and(|max_size| size > max_size)
+                || self.filter.is_some_and(|filter| !filter(path, &metadata))
+            {
+                continue;
+            }
+
+            if let Some(limit) = options.max_file_size {
+                if size > limit {
+                    return Err(KushnError::FileTooLarge {
+                        path: path.to_path_buf(),
+                        size,
+                        limit,
+                    });
+                }
+            }
+
+            if let Some(limit) = options.max_files {
+                if self.hashed >= limit {
+                    return Err(KushnError::FileLimitExceeded { limit });
+                }
+            }
+
+            let link_target = match options.symlinks {
+                SymlinkPolicy::FollowAndRecord if entry.path_is_symlink() => fs::canonicalize(path)
+                    .ok()
+                    .map(|target| target.to_string_lossy().into_owned()),
+                SymlinkPolicy::HashLinkTarget if is_link => fs::read_link(path)
+                    .ok()
+                    .map(|target| target.to_string_lossy().into_owned()),
+                _ => None,
+            };
+
+            if self.list_only {
+                self.hashed += 1;
+                let entry_path = self.entry_path(path_string.clone());
+                self.selected = Some(SelectedFile {
+                    path: path.to_path_buf(),
+                    path_string,
+                    entry_path: entry_path.clone(),
+                    metadata,
+                    is_link,
+                    link_target,
+                });
+                return Ok(Some(FileHash::new(entry_path, String::new())));
+            }
+
+            let bound_path = options.path_binding.then_some(path_string.as_str());
+            let mut uniform_check = UniformByteCheck::default();
+            let mut sniffer = ContentSniffer::default();
+            let decompress = !is_link && options.decompresses(path);
+            let cache = options
+                .cache
+                .as_ref()
+                .filter(|_| options.uses_cache() && !decompress && !is_link);
+            let cache_key = cache.and_then(|_| CacheKey::from_metadata(&metadata));
+            let reused =
+                if self.previous.is_empty() || options.reads_content() || decompress || is_link {
+                    None
+                } else {
+                    self.previous
+                        .get(self.entry_path(path_string.clone()).as_str())
+                        .filter(|previous| {
+                            previous.algorithm.unwrap_or_default() == options.algorithm
+                                && previous.size == Some(metadata.len())
+                                && previous.modified.is_some()
+                                && previous.modified == metadata.modified().ok()
+                        })
+                        .and_then(|previous| options.encoding.to_hex(&previous.hash))
+                };
+            let cached = reused.or_else(|| {
+                cache
+                    .zip(cache_key)
+                    .and_then(|(cache, key)| cache.lookup(&key))
+            });
+
+            let hash_started = Instant::now();
+            let hash = match cached {
+                Some(hash) => Ok(hash),
+                None if is_link => fs::read_link(path).and_then(|target| {
+                    hash_reader_inspecting(
+                        &mut link_bytes(&target).as_slice(),
+                        options.algorithm,
+                        bound_path,
+                        |chunk| inspect_chunk(chunk, options, &mut uniform_check, &mut sniffer),
+                    )
+                }),
+                None => open_file(path).and_then(|mut file| {
+                    let decoded = if decompress {
+                        let mut decoder = MultiGzDecoder::new(&file);
+                        hash_reader_inspecting(
+                            &mut decoder,
+                            options.algorithm,
+                            bound_path,
+                            |chunk| inspect_chunk(chunk, options, &mut uniform_check, &mut sniffer),
+                        )
+                        .ok()
+                    } else {
+                        None
+                    };
+                    let hash = match decoded {
+                        Some(hash) => hash,
+                        None => {
+                            if decompress {
+                                self.report
+                                    .warnings
+                                    .push(Warning::DecompressionFailed(path.to_path_buf()));
+                                uniform_check = UniformByteCheck::default();
+                                sniffer = ContentSniffer::default();
+                                file.seek(SeekFrom::Start(0))?;
+                            }
+                            let inspect = |chunk: &[u8]| {
+                                inspect_chunk(chunk, options, &mut uniform_check, &mut sniffer)
+                            };
+                            if options.mmap && metadata.len() >= MMAP_MIN_SIZE {
+                                hash_file_mapped(&mut file, options.algorithm, bound_path, inspect)?
+                            } else {
+                                hash_reader_inspecting(
+                                    &mut file,
+                                    options.algorithm,
+                                    bound_path,
+                                    inspect,
+                                )?
+                            }
+                        }
+                    };
+                    if is_unstable(&metadata, &file) {
+                        self.report
+                            .warnings
+                            .push(Warning::Unstable(path.to_path_buf()));
+                    }
+                    Ok(hash)
+                }),
+            };
+            self.report.timings.hashing += hash_started.elapsed();
+            if let (Ok(hash), Some(cache), Some(key)) = (&hash, cache, cache_key) {
+                cache.insert(key, hash.clone());
+            }
+            let path_string = self.entry_path(path_string);
+            if hash.is_ok() && uniform_check.is_uniform() {
+                self.report.uniform_files.push(path_string.clone());
+            }
+            self.processed += 1;
+            if let Some(progress) = self.progress.as_mut() {
+                progress(path, self.processed, self.total);
+            }
+            match hash {
+                Ok(hash) => {
+                    let (uid, gid) = if options.ownership {
+                        ownership(&metadata)
+                    } else {
+                        (None, None)
+                    };
+                    let entry = FileHash {
+                        link_target,
+                        content_type: options.content_type.then(|| sniffer.content_type()),
+                        uid,
+                        gid,
+                        algorithm: options.recorded_algorithm(),
+                        size: options.file_metadata.then_some(metadata.len()),
+                        modified: options
+                            .file_metadata
+                            .then(|| metadata.modified().ok())
+                            .flatten(),
+                        ..FileHash::new(path_string, options.written_hash(hash))
+                    };
+                    self.hashed += 1;
+                    return Ok(Some(entry));
+                }
+                Err(err) => self
+                    .report
+                    .record_failure(path.to_path_buf(), err.into(), options)?,
+            }
+        }
+    }
+
+    /// Applies every filter and limit of the scan like `next_entry`, but hands
+    /// out the files to hash instead of hashing them.
+    pub(crate) fn next_selected(&mut self) -> KushnResult<Option<Selected>> {
+        self.list_only = true;
+        Ok(self.next_entry()?.map(|entry| match self.selected.take() {
+            Some(file) => Selected::File(file),
+            None => Selected::Entry(entry),
+        }))
+    }
+
+    /// Everything the scan collected but the entries.
+    pub(crate) fn into_report(self) -> ScanReport {
+        self.report
+    }
+
+    /// The path recorded for a file at `path_string` relative to the root.
+    fn entry_path(&self, path_string: String) -> String {
+        let path_string = match &self.root_name {
+            Some(root_name) if path_string.is_empty() => root_name.clone(),
+            Some(root_name) => format!("{}/{}", root_name, path_string),
+            None => path_string,
+        };
+        if self.options.dot_prefix {
+            format!("./{}", path_string)
+        } else {
+            path_string
+        }
+    }
+}
+
+fn scan<'o, F>(
+    directory_path: &Path,
+    options: &'o ProcessOptions,
+    cancel: Option<&'o AtomicBool>,
+    mut on_entry: F,
+    progress: Option<&'o mut ProgressFn<'o>>,
+    filter: Option<&'o FilterFn<'o>>,
+) -> KushnResult<ScanReport>
+where
+    F: FnMut(&FileHash),
+{
+    let total_started = Instant::now();
+    let mut scanner = Scanner::new(directory_path, Cow::Borrowed(options), cancel, progress)?;
+    scanner.filter = filter;
+    let mut entries = Vec::new();
+    while let Some(entry) = scanner.next_entry()? {
+        on_entry(&entry);
+        entries.push(entry);
+    }
+    let mut report = ScanReport {
+        entries,
+        ..scanner.report
+    };
+
+    if let Some(cache) = &options.cache {
+        cache.save()?;
+    }
+    if options.dedup_content {
+        dedup_by_content(&mut report.entries);
+    }
+    if options.sorted {
+        sort_entries(&mut report.entries);
+    }
+    if !options.allow_empty && !report.interrupted && report.entries.is_empty() {
+        return Err(KushnError::NoFilesFound {
+            path: directory_path.to_path_buf(),
+        });
+    }
+    report.timings.total = total_started.elapsed();
+    Ok(report)
+}
+
+pub fn process_directory_with<P: AsRef<Path>>(
+    directory_path: P,
+    options: &ProcessOptions,
+) -> KushnResult<Vec<FileHash>> {
+    scan_directory(directory_path, options).map(|report| report.entries)
+}
+
+/// Scans like [`process_directory_with`], but hashes every file it can instead
+/// of stopping at the first one that cannot be read, and returns those that
+/// failed with their errors. Unlike [`ProcessOptions::with_continue_on_error`],
+/// denied access and files that vanished during the scan are failures here, not
+/// warnings. Exceeded limits such as [`ProcessOptions::with_max_files`] still
+/// fail the scan.
+pub fn process_directory_with_errors<P: AsRef<Path>>(
+    directory_path: P,
+    options: &ProcessOptions,
+) -> KushnResult<(Vec<FileHash>, Vec<FileError>)> {
+    let mut options = options.clone().with_continue_on_error(true);
+    options.failures_as_errors = true;
+    scan_directory(directory_path, &options).map(|report| (report.entries, report.errors))
+}
+
+/// Scans like [`process_directory_with`], reusing the hash recorded in `previous`
+/// for every file whose path, size and modification time are unchanged instead
+/// of reading it again. Only new or changed files are hashed. Entries always
+/// record size and mtime, as with [`ProcessOptions::with_file_metadata`], so the
+/// result can serve as `previous` for the next run.
+///
+/// Unchanged metadata is taken to mean unchanged content. A file rewritten at
+/// the same size within the filesystem's mtime granularity, or whose mtime was
+/// reset, keeps its old hash; do a full scan when that matters. Hashes are not
+/// reused when the algorithm differs or options that read the content, such as
+/// path binding, are set. `previous` must use the same encoding as `options`.
+pub fn process_directory_incremental<P: AsRef<Path>>(
+    directory_path: P,
+    previous: &[FileHash],
+    options: &ProcessOptions,
+) -> KushnResult<Vec<FileHash>> {
+    let directory_path = directory_path.as_ref();
+    let options = options.clone().with_file_metadata(true);
+    let mut scanner = Scanner::new(directory_path, Cow::Borrowed(&options), None, None)?;
+    scanner.previous = previous
+        .iter()
+        .map(|entry| (entry.path.as_str(), entry))
+        .collect();
+    let mut entries = Vec::new();
+    while let Some(entry) = scanner.next_entry()? {
+        entries.push(entry);
+    }
+    if let Some(cache) = &options.cache {
+        cache.save()?;
+    }
+    if options.sorted {
+        sort_entries(&mut entries);
+    }
+    if !options.allow_empty && entries.is_empty() {
+        return Err(KushnError::NoFilesFound {
+            path: directory_path.to_path_buf(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Scans like [`process_directory_with`] and reports where the time went, to
+/// tell I/O-bound scans from CPU-bound ones.
+pub fn process_directory_timed<P: AsRef<Path>>(
+    directory_path: P,
+    options: &ProcessOptions,
+) -> KushnResult<(Vec<FileHash>, PhaseTimings)> {
+    scan_directory(directory_path, options).map(|report| (report.entries, report.timings))
+}
+
+pub fn process_directory<P: AsRef<Path>>(directory_path: P, ignore: &[String]) -> Vec<FileHash> {
+    let options = ProcessOptions::new()
+        .with_ignore(ignore.to_vec())
+        .with_continue_on_error(true);
+
+    match scan_directory(directory_path, &options) {
+        Ok(report) => {
+            for warning in &report.warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            for (path, err) in &report.errors {
+                eprintln!("Error processing entry {}: {}", path.display(), err);
+            }
+            report.entries
+        }
+        Err(err) => {
+            eprintln!("Error processing directory: {}", err);
+            Vec::new()
+        }
+    }
+}
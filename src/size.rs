@@ -0,0 +1,28 @@
+use crate::{KushnError, KushnResult};
+
+/// Parses a byte count such as `512`, `10MB` or `1GiB`. Decimal suffixes (`KB`,
+/// `MB`, `GB`, `TB`) are powers of 1000, binary ones (`KiB`, `MiB`, ...) and the
+/// bare single-letter forms (`K`, `M`, `G`, `T`) are powers of 1024.
+pub fn parse_size(value: &str) -> KushnResult<u64> {
+    let invalid = || KushnError::InvalidSize(value.to_owned());
+    let trimmed = value.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (number, suffix) = trimmed.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+
+    let multiplier: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000u64.pow(2),
+        "gb" => 1000u64.pow(3),
+        "tb" => 1000u64.pow(4),
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        _ => return Err(invalid()),
+    };
+    number.checked_mul(multiplier).ok_or_else(invalid)
+}
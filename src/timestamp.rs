@@ -0,0 +1,25 @@
+//! Serde helpers writing `Option<SystemTime>` as an RFC 3339 UTC timestamp, with
+//! a fractional second only when the time has one.
+
+use std::time::SystemTime;
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serializer};
+
+pub(crate) fn serialize<S: Serializer>(
+    time: &Option<SystemTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serializer.collect_str(&humantime::format_rfc3339(*time)),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<SystemTime>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|time| humantime::parse_rfc3339(&time).map_err(D::Error::custom))
+        .transpose()
+}
@@ -0,0 +1,160 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::manifest::parse_coreutils_sums;
+#[cfg(feature = "http")]
+use crate::manifest::parse_manifest;
+use crate::options::ProcessOptions;
+use crate::patterns::IgnoreMatcher;
+use crate::scan::process_directory_with;
+use crate::{FileHash, KushnResult, Warning};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub matched: Vec<String>,
+    pub changed: Vec<String>,
+    pub missing: Vec<String>,
+    pub added: Vec<String>,
+    /// Warnings about the manifest itself, such as a newer schema version, for
+    /// manifests that are read by the verify function.
+    pub warnings: Vec<Warning>,
+}
+
+impl VerifyReport {
+    pub fn is_clean(&self) -> bool {
+        self.changed.is_empty() && self.missing.is_empty() && self.added.is_empty()
+    }
+}
+
+/// Compares the files on disk against `manifest`. Paths matching `ignore` are left
+/// out of both the scan and the manifest, so expected churn is never reported.
+pub fn verify_directory<P: AsRef<Path>>(
+    directory_path: P,
+    manifest: &[FileHash],
+    ignore: &[String],
+) -> KushnResult<VerifyReport> {
+    let options = ProcessOptions::new().with_ignore(ignore.to_vec());
+    verify_directory_with(directory_path, manifest, &options)
+}
+
+pub fn verify_directory_with<P: AsRef<Path>>(
+    directory_path: P,
+    manifest: &[FileHash],
+    options: &ProcessOptions,
+) -> KushnResult<VerifyReport> {
+    let current = scan_current(directory_path.as_ref(), options)?;
+    compare_entries(&current, manifest, options)
+}
+
+/// The tree as it is now. A tree left without files is not an error here: its
+/// entries are reported missing instead.
+fn scan_current(directory_path: &Path, options: &ProcessOptions) -> KushnResult<Vec<FileHash>> {
+    process_directory_with(directory_path, &options.clone().with_allow_empty(true))
+}
+
+/// Verifies like [`verify_directory_with`] and additionally returns `manifest`
+/// brought up to date with the tree: changed hashes are replaced, added files
+/// appended and missing files dropped. Entries excluded by the ignore patterns
+/// are kept as they are, since they were not part of the check.
+pub fn verify_and_update<P: AsRef<Path>>(
+    directory_path: P,
+    manifest: &[FileHash],
+    options: &ProcessOptions,
+) -> KushnResult<(VerifyReport, Vec<FileHash>)> {
+    let current = scan_current(directory_path.as_ref(), options)?;
+    let report = compare_entries(&current, manifest, options)?;
+    let matcher = IgnoreMatcher::new(&options.ignore, !options.case_insensitive)?;
+
+    let mut current_by_path: HashMap<Cow<'_, str>, &FileHash> = current
+        .iter()
+        .map(|entry| (options.path_key(&entry.path), entry))
+        .collect();
+    let mut updated = Vec::with_capacity(current.len());
+    for entry in manifest {
+        if matcher.is_path_ignored(Path::new(&entry.path)) {
+            updated.push(entry.clone());
+        } else if let Some(current_entry) = current_by_path.remove(&options.path_key(&entry.path)) {
+            updated.push(current_entry.clone());
+        }
+    }
+    updated.extend(
+        current
+            .iter()
+            .filter(|entry| current_by_path.contains_key(&options.path_key(&entry.path)))
+            .cloned(),
+    );
+    Ok((report, updated))
+}
+
+fn compare_entries(
+    current: &[FileHash],
+    manifest: &[FileHash],
+    options: &ProcessOptions,
+) -> KushnResult<VerifyReport> {
+    let matcher = IgnoreMatcher::new(&options.ignore, !options.case_insensitive)?;
+
+    let mut report = VerifyReport::default();
+    let mut expected: HashMap<Cow<'_, str>, &FileHash> = HashMap::new();
+    for entry in manifest {
+        if matcher.is_path_ignored(Path::new(&entry.path)) {
+            continue;
+        }
+        if let Some(shadowed) = expected.insert(options.path_key(&entry.path), entry) {
+            // Only one of them can exist where paths differ in case only.
+            if shadowed.path != entry.path {
+                report.missing.push(shadowed.path.clone());
+            }
+        }
+    }
+
+    for entry in current {
+        match expected.remove(&options.path_key(&entry.path)) {
+            Some(expected) if expected.hash == entry.hash => {
+                report.matched.push(entry.path.clone())
+            }
+            Some(_) => report.changed.push(entry.path.clone()),
+            None => report.added.push(entry.path.clone()),
+        }
+    }
+    report
+        .missing
+        .extend(expected.into_values().map(|entry| entry.path.clone()));
+
+    report.matched.sort();
+    report.changed.sort();
+    report.missing.sort();
+    report.added.sort();
+    Ok(report)
+}
+
+/// Verifies a directory against a `SHA256SUMS`-style checksum file, as shipped
+/// alongside many release artifacts.
+pub fn verify_against_coreutils<P: AsRef<Path>, Q: AsRef<Path>>(
+    directory_path: P,
+    sums_file: Q,
+    options: &ProcessOptions,
+) -> KushnResult<VerifyReport> {
+    let manifest = parse_coreutils_sums(&fs::read_to_string(sums_file)?)?;
+    verify_directory_with(directory_path, &manifest, options)
+}
+
+/// Fetches a JSON manifest, in any layout Kushn writes, from `url` and verifies
+/// the directory against it, with the algorithm the manifest records if any.
+/// Non-success HTTP statuses are reported as errors.
+#[cfg(feature = "http")]
+pub fn verify_against_url<P: AsRef<Path>>(
+    directory_path: P,
+    url: &str,
+    options: &ProcessOptions,
+) -> KushnResult<VerifyReport> {
+    let body = reqwest::blocking::get(url)?.error_for_status()?.text()?;
+    let (manifest, warnings) = parse_manifest(&body, url)?;
+    let options = match manifest.first().and_then(|entry| entry.algorithm) {
+        Some(algorithm) => options.clone().with_algorithm(algorithm),
+        None => options.clone(),
+    };
+    let report = verify_directory_with(directory_path, &manifest, &options)?;
+    Ok(VerifyReport { warnings, ..report })
+}
@@ -0,0 +1,287 @@
+use std::collections::VecDeque;
+use std::fs::{self, FileType, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use walkdir::WalkDir;
+
+use crate::options::{ProcessOptions, SymlinkPolicy};
+use crate::KushnError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Traversal {
+    DepthFirst,
+    BreadthFirst,
+}
+
+pub(crate) struct WalkEntry {
+    path: PathBuf,
+    file_type: FileType,
+    path_is_symlink: bool,
+    follow_links: bool,
+}
+
+impl WalkEntry {
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    pub(crate) fn path_is_symlink(&self) -> bool {
+        self.path_is_symlink
+    }
+
+    pub(crate) fn metadata(&self) -> io::Result<Metadata> {
+        if self.follow_links {
+            fs::metadata(&self.path)
+        } else {
+            fs::symlink_metadata(&self.path)
+        }
+    }
+}
+
+pub(crate) type WalkItem = Result<WalkEntry, (PathBuf, KushnError)>;
+
+/// Walks `root` in the order selected by the options. `skip_dir` is consulted for
+/// every directory below the root; directories it returns `true` for are neither
+/// yielded nor descended into.
+pub(crate) fn walk<'a>(
+    root: &Path,
+    options: &ProcessOptions,
+    skip_dir: impl Fn(&Path) -> bool + 'a,
+) -> Box<dyn Iterator<Item = WalkItem> + 'a> {
+    let follow_links = matches!(
+        options.symlinks,
+        SymlinkPolicy::Follow | SymlinkPolicy::FollowAndRecord
+    );
+    match options.traversal {
+        Traversal::DepthFirst => {
+            let mut walker = WalkDir::new(root)
+                .follow_links(follow_links)
+                .same_file_system(options.same_file_system);
+            // `WalkDir` counts the root's children as depth 1.
+            if let Some(max_depth) = options.max_depth {
+                walker = walker.max_depth(max_depth.saturating_add(1));
+            }
+            let error_root = root.to_path_buf();
+            let walker = walker
+                .into_iter()
+                .filter_entry(move |entry| {
+                    entry.depth() == 0 || !entry.file_type().is_dir() || !skip_dir(entry.path())
+                })
+                .map(move |entry| match entry {
+                    Ok(entry) => Ok(WalkEntry {
+                        path: entry.path().to_path_buf(),
+                        file_type: entry.file_type(),
+                        path_is_symlink: entry.path_is_symlink(),
+                        follow_links,
+                    }),
+                    Err(err) => {
+                        let path = err.path().unwrap_or(&error_root).to_path_buf();
+                        Err((path, KushnError::Walk(err)))
+                    }
+                });
+            Box::new(walker)
+        }
+        Traversal::BreadthFirst => Box::new(BreadthFirstWalk::new(
+            root,
+            follow_links,
+            options.same_file_system,
+            options.max_depth,
+            skip_dir,
+        )),
+    }
+}
+
+// Canonical paths of the directories leading to a queued directory, used to
+// detect symlink loops the same way `WalkDir` does: only links back to an
+// ancestor are loops, so two links to the same sibling tree are both walked.
+struct Ancestor {
+    path: PathBuf,
+    parent: Option<Rc<Ancestor>>,
+}
+
+fn is_ancestor(ancestors: &Option<Rc<Ancestor>>, path: &Path) -> bool {
+    let mut current = ancestors.as_ref();
+    while let Some(ancestor) = current {
+        if ancestor.path == path {
+            return true;
+        }
+        current = ancestor.parent.as_ref();
+    }
+    false
+}
+
+#[cfg(unix)]
+fn device_id(metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device_id(_metadata: &Metadata) -> Option<u64> {
+    None
+}
+
+struct BreadthFirstWalk<F> {
+    queue: VecDeque<(PathBuf, usize, Option<Rc<Ancestor>>)>,
+    pending: VecDeque<WalkItem>,
+    root: Option<PathBuf>,
+    root_device: Option<u64>,
+    follow_links: bool,
+    same_file_system: bool,
+    max_depth: Option<usize>,
+    skip_dir: F,
+}
+
+impl<F: Fn(&Path) -> bool> BreadthFirstWalk<F> {
+    fn new(
+        root: &Path,
+        follow_links: bool,
+        same_file_system: bool,
+        max_depth: Option<usize>,
+        skip_dir: F,
+    ) -> Self {
+        BreadthFirstWalk {
+            queue: VecDeque::new(),
+            pending: VecDeque::new(),
+            root: Some(root.to_path_buf()),
+            root_device: None,
+            follow_links,
+            same_file_system,
+            max_depth,
+            skip_dir,
+        }
+    }
+
+    fn visit_root(&mut self, root: PathBuf) {
+        let metadata = match fs::metadata(&root) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                self.pending.push_back(Err((root, err.into())));
+                return;
+            }
+        };
+        self.root_device = device_id(&metadata);
+        let path_is_symlink = fs::symlink_metadata(&root)
+            .map(|metadata| metadata.file_type().is_symlink())
+            .unwrap_or(false);
+
+        if metadata.is_dir() {
+            let ancestors = self.ancestors_for(&root, None);
+            self.queue.push_back((root.clone(), 0, ancestors));
+        }
+        self.pending.push_back(Ok(WalkEntry {
+            path: root,
+            file_type: metadata.file_type(),
+            path_is_symlink,
+            follow_links: self.follow_links,
+        }));
+    }
+
+    fn ancestors_for(&self, path: &Path, parent: Option<Rc<Ancestor>>) -> Option<Rc<Ancestor>> {
+        if !self.follow_links {
+            return None;
+        }
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        Some(Rc::new(Ancestor { path, parent }))
+    }
+
+    fn read_directory(
+        &mut self,
+        directory: PathBuf,
+        depth: usize,
+        ancestors: Option<Rc<Ancestor>>,
+    ) {
+        let read_dir = match fs::read_dir(&directory) {
+            Ok(read_dir) => read_dir,
+            Err(err) => {
+                self.pending.push_back(Err((directory, err.into())));
+                return;
+            }
+        };
+
+        for child in read_dir {
+            let child = match child {
+                Ok(child) => child,
+                Err(err) => {
+                    self.pending.push_back(Err((directory.clone(), err.into())));
+                    continue;
+                }
+            };
+            let path = child.path();
+            let mut file_type = match child.file_type() {
+                Ok(file_type) => file_type,
+                Err(err) => {
+                    self.pending.push_back(Err((path, err.into())));
+                    continue;
+                }
+            };
+
+            let path_is_symlink = file_type.is_symlink();
+            if path_is_symlink && self.follow_links {
+                match fs::metadata(&path) {
+                    Ok(metadata) => file_type = metadata.file_type(),
+                    Err(err) => {
+                        self.pending.push_back(Err((path, err.into())));
+                        continue;
+                    }
+                }
+            }
+
+            if file_type.is_dir() {
+                if (self.skip_dir)(&path) {
+                    continue;
+                }
+                if self.same_file_system && self.root_device.is_some() {
+                    let device = fs::metadata(&path).ok().and_then(|m| device_id(&m));
+                    if device != self.root_device {
+                        continue;
+                    }
+                }
+                if self.follow_links {
+                    let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+                    if is_ancestor(&ancestors, &canonical) {
+                        let err = KushnError::SymlinkLoop { path: path.clone() };
+                        self.pending.push_back(Err((path, err)));
+                        continue;
+                    }
+                }
+                if self.max_depth.is_none_or(|max_depth| depth < max_depth) {
+                    let child_ancestors = self.ancestors_for(&path, ancestors.clone());
+                    self.queue
+                        .push_back((path.clone(), depth + 1, child_ancestors));
+                }
+            }
+
+            self.pending.push_back(Ok(WalkEntry {
+                path,
+                file_type,
+                path_is_symlink,
+                follow_links: self.follow_links,
+            }));
+        }
+    }
+}
+
+impl<F: Fn(&Path) -> bool> Iterator for BreadthFirstWalk<F> {
+    type Item = WalkItem;
+
+    fn next(&mut self) -> Option<WalkItem> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            }
+            if let Some(root) = self.root.take() {
+                self.visit_root(root);
+                continue;
+            }
+            let (directory, depth, ancestors) = self.queue.pop_front()?;
+            self.read_directory(directory, depth, ancestors);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::KushnError;
+
+/// Non-fatal conditions met during a scan. With
+/// [`ProcessOptions::with_continue_on_error`](crate::ProcessOptions::with_continue_on_error)
+/// the recognizable failures (denied access, vanished files, broken or looping
+/// links) are reported here instead of in `ScanReport::errors`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    SkippedSymlink(PathBuf),
+    SkippedSpecialFile(PathBuf),
+    DanglingSymlink(PathBuf),
+    SymlinkLoop(PathBuf),
+    AccessDenied(PathBuf),
+    Vanished(PathBuf),
+    /// The file's size or mtime changed while it was being hashed.
+    Unstable(PathBuf),
+    /// A file selected for decompression was not valid gzip and was hashed as
+    /// stored on disk instead.
+    DecompressionFailed(PathBuf),
+    /// The manifest declares a schema version newer than this release knows;
+    /// fields it does not recognize were ignored.
+    UnknownSchemaVersion(PathBuf, u32),
+}
+
+impl Warning {
+    pub fn path(&self) -> &Path {
+        match self {
+            Warning::SkippedSymlink(path)
+            | Warning::SkippedSpecialFile(path)
+            | Warning::DanglingSymlink(path)
+            | Warning::SymlinkLoop(path)
+            | Warning::AccessDenied(path)
+            | Warning::Vanished(path)
+            | Warning::Unstable(path)
+            | Warning::DecompressionFailed(path)
+            | Warning::UnknownSchemaVersion(path, _) => path,
+        }
+    }
+
+    pub(crate) fn from_error(path: &Path, err: &KushnError) -> Option<Warning> {
+        let kind = match err {
+            KushnError::SymlinkLoop { .. } => {
+                return Some(Warning::SymlinkLoop(path.to_path_buf()))
+            }
+            KushnError::Walk(err) if err.loop_ancestor().is_some() => {
+                return Some(Warning::SymlinkLoop(path.to_path_buf()))
+            }
+            KushnError::Walk(err) => err.io_error()?.kind(),
+            KushnError::Io(err) => err.kind(),
+            _ => return None,
+        };
+
+        let path = path.to_path_buf();
+        match kind {
+            io::ErrorKind::PermissionDenied => Some(Warning::AccessDenied(path)),
+            io::ErrorKind::NotFound => {
+                let is_symlink = path
+                    .symlink_metadata()
+                    .map(|metadata| metadata.file_type().is_symlink())
+                    .unwrap_or(false);
+                if is_symlink {
+                    Some(Warning::DanglingSymlink(path))
+                } else {
+                    Some(Warning::Vanished(path))
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Warning::SkippedSymlink(_) => "skipped symlink",
+            Warning::SkippedSpecialFile(_) => "skipped special file",
+            Warning::DanglingSymlink(_) => "dangling symlink",
+            Warning::SymlinkLoop(_) => "symlink loop",
+            Warning::AccessDenied(_) => "access denied",
+            Warning::Vanished(_) => "vanished during scan",
+            Warning::Unstable(_) => "changed while being hashed",
+            Warning::DecompressionFailed(_) => "not valid gzip, hashed as stored",
+            Warning::UnknownSchemaVersion(_, version) => {
+                return write!(
+                    f,
+                    "{}: unknown manifest schema version {}, newer fields were ignored",
+                    self.path().display(),
+                    version
+                );
+            }
+        };
+        write!(f, "{}: {}", self.path().display(), reason)
+    }
+}
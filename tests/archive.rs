@@ -0,0 +1,73 @@
+mod common;
+
+use common::{paths, TempTree};
+use kushn::{process_directory_with, process_tar, Encoding, ProcessOptions};
+
+fn tar_of(tree: &TempTree, files: &[&str]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for file in files {
+        builder
+            .append_path_with_name(tree.path().join(file), file)
+            .unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+#[test]
+fn tar_entries_match_the_directory_scan() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "alpha").file("sub/b.txt", "beta");
+    let archive = tar_of(&tree, &["a.txt", "sub/b.txt"]);
+    let configurations = [
+        ProcessOptions::new(),
+        ProcessOptions::new().with_encoding(Encoding::Base64),
+        ProcessOptions::new()
+            .with_encoding(Encoding::HexUpper)
+            .with_truncate(Some(12)),
+    ];
+    for options in configurations {
+        let scanned = process_directory_with(tree.path(), &options).unwrap();
+        assert_eq!(process_tar(archive.as_slice(), &options).unwrap(), scanned);
+    }
+}
+
+#[test]
+fn tar_entries_are_filtered_and_sorted_like_the_directory_scan() {
+    let tree = TempTree::new();
+    tree.file("z.txt", "last")
+        .file("build/out.txt", "out")
+        .file("a.log", "log")
+        .file("sub/b.txt", "beta")
+        .file("sub/big.txt", &"x".repeat(100))
+        .file("sub/c.rs", "fn main() {}");
+    let archive = tar_of(
+        &tree,
+        &[
+            "z.txt",
+            "build/out.txt",
+            "a.log",
+            "sub/c.rs",
+            "sub/big.txt",
+            "sub/b.txt",
+        ],
+    );
+    let configurations = [
+        ProcessOptions::new(),
+        ProcessOptions::new().with_ignore(vec!["*.log".to_owned(), "build/".to_owned()]),
+        ProcessOptions::new().with_include(vec!["*.txt".to_owned()]),
+        ProcessOptions::new()
+            .with_min_size(Some(4))
+            .with_max_size(Some(50)),
+    ];
+    for options in configurations {
+        let scanned = process_directory_with(tree.path(), &options).unwrap();
+        assert_eq!(process_tar(archive.as_slice(), &options).unwrap(), scanned);
+    }
+
+    let options = ProcessOptions::new().with_ignore(vec!["*.log".to_owned(), "build/".to_owned()]);
+    let entries = process_tar(archive.as_slice(), &options).unwrap();
+    assert_eq!(
+        paths(&entries),
+        ["sub/b.txt", "sub/big.txt", "sub/c.rs", "z.txt"]
+    );
+}
@@ -0,0 +1,37 @@
+use kushn::{bloom_contains, build_hash_bloom, BloomFilter, FileHash};
+
+fn entries() -> Vec<FileHash> {
+    (0..1000)
+        .map(|index| FileHash::new(format!("{}.txt", index), format!("{:064x}", index)))
+        .collect()
+}
+
+#[test]
+fn present_hashes_always_match_and_absent_ones_rarely() {
+    let entries = entries();
+    let filter = build_hash_bloom(&entries, 0.01).unwrap();
+    assert!(entries
+        .iter()
+        .all(|entry| bloom_contains(&filter, &entry.hash)));
+    assert!(bloom_contains(
+        &filter,
+        &entries[7].hash.to_ascii_uppercase()
+    ));
+
+    let false_positives = (1000..11_000)
+        .filter(|index| bloom_contains(&filter, &format!("{:064x}", index)))
+        .count();
+    assert!(false_positives < 300, "{} false positives", false_positives);
+}
+
+#[test]
+fn filters_survive_serialization() {
+    let entries = entries();
+    let filter = build_hash_bloom(&entries, 0.01).unwrap();
+    let json = serde_json::to_string(&filter).unwrap();
+    let loaded: BloomFilter = serde_json::from_str(&json).unwrap();
+    assert!(entries
+        .iter()
+        .all(|entry| bloom_contains(&loaded, &entry.hash)));
+    assert!(build_hash_bloom(&entries, 1.0).is_err());
+}
@@ -0,0 +1,32 @@
+// The cache is keyed by inode and only hits on Unix.
+#![cfg(unix)]
+
+mod common;
+
+use common::TempTree;
+use kushn::{process_directory_with, CacheStore, ProcessOptions, DEFAULT_CACHE_FILE};
+
+#[test]
+fn second_run_reads_no_unchanged_file() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a").file("sub/b.txt", "b");
+    let state = TempTree::new();
+    let cache_file = state.path().join(DEFAULT_CACHE_FILE);
+    let scan = || {
+        let cache = CacheStore::file(&cache_file).unwrap();
+        let options = ProcessOptions::new().with_cache(cache.clone());
+        let entries = process_directory_with(tree.path(), &options).unwrap();
+        (entries, cache)
+    };
+
+    let (first, cache) = scan();
+    assert_eq!((cache.hits(), cache.misses()), (0, 2));
+    let (second, cache) = scan();
+    assert_eq!((cache.hits(), cache.misses()), (2, 0));
+    assert_eq!(first, second);
+
+    tree.file("a.txt", "changed");
+    let (third, cache) = scan();
+    assert_eq!((cache.hits(), cache.misses()), (1, 1));
+    assert_ne!(third[0].hash, first[0].hash);
+}
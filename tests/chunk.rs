@@ -0,0 +1,67 @@
+mod common;
+
+use common::TempTree;
+use kushn::{chunk_file_cdc, KushnError, MIN_AVG_CHUNK_SIZE};
+
+#[test]
+fn chunks_cover_the_whole_file() {
+    let tree = TempTree::new();
+    let contents: String = (0..20_000u32)
+        .map(|i| char::from(b'a' + (i * 7 % 26) as u8))
+        .collect();
+    tree.file("data.txt", &contents);
+    let chunks = chunk_file_cdc(tree.path().join("data.txt"), 256).unwrap();
+    assert!(chunks.len() > 1);
+    assert_eq!(
+        chunks.iter().map(|(length, _)| length).sum::<u64>(),
+        contents.len() as u64
+    );
+}
+
+#[test]
+fn rejects_unusable_average_sizes() {
+    let tree = TempTree::new();
+    tree.file("data.txt", "data");
+    let path = tree.path().join("data.txt");
+    for avg in [MIN_AVG_CHUNK_SIZE - 1, usize::MAX, usize::MAX / 4] {
+        assert!(matches!(
+            chunk_file_cdc(&path, avg),
+            Err(KushnError::InvalidSize(_))
+        ));
+    }
+    assert_eq!(chunk_file_cdc(&path, 1 << 40).unwrap().len(), 1);
+}
+
+fn noise(length: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..length)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+#[test]
+fn early_insertions_only_change_nearby_chunks() {
+    let tree = TempTree::new();
+    let original = noise(200_000);
+    let mut edited = original.clone();
+    edited.splice(1000..1000, b"inserted bytes".iter().copied());
+    std::fs::write(tree.path().join("original.bin"), &original).unwrap();
+    std::fs::write(tree.path().join("edited.bin"), &edited).unwrap();
+
+    let before = chunk_file_cdc(tree.path().join("original.bin"), 1024).unwrap();
+    let after = chunk_file_cdc(tree.path().join("edited.bin"), 1024).unwrap();
+    assert!(before.len() > 20, "{}", before.len());
+    let new_chunks = after.iter().filter(|chunk| !before.contains(chunk)).count();
+    assert!(
+        new_chunks <= 2,
+        "{} of {} chunks changed",
+        new_chunks,
+        after.len()
+    );
+    assert_eq!(before[before.len() - 10..], after[after.len() - 10..]);
+}
@@ -0,0 +1,354 @@
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use common::TempTree;
+use kushn::{calculate_file_hash, calculate_hash_reader, FileHash, HashAlgorithm};
+
+fn kushn(tree: &TempTree, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_kushn"))
+        .args(args)
+        .current_dir(tree.path())
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn verify_keeps_files_named_like_the_default_output() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a")
+        .file("kushn_result.json", "not a manifest");
+    assert!(kushn(&tree, &["--name", "n.json"]).status.success());
+    let manifest = fs::read_to_string(tree.path().join("n.json")).unwrap();
+    assert!(manifest.contains("\"kushn_result.json\""));
+
+    let output = kushn(&tree, &["--verify", "n.json"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(stdout(&output).contains("2 matched, 0 modified, 0 missing, 0 added."));
+}
+
+#[test]
+fn verify_of_an_emptied_tree_lists_the_missing_files() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a").file("b.txt", "b");
+    assert!(kushn(&tree, &[]).status.success());
+    fs::remove_file(tree.path().join("a.txt")).unwrap();
+    fs::remove_file(tree.path().join("b.txt")).unwrap();
+
+    let output = kushn(&tree, &["--verify", "kushn_result.json"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("- a.txt") && stdout.contains("- b.txt"));
+}
+
+#[test]
+fn print_root_hash_prints_only_the_hash() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a").file("sub/b.txt", "b");
+    let output = kushn(&tree, &["--print-root-hash"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    let hash = stdout.strip_suffix('\n').unwrap();
+    assert_eq!(hash.len(), 64);
+    assert!(hash.chars().all(|c| c.is_ascii_hexdigit()), "{:?}", stdout);
+    assert!(!tree.path().join("kushn_result.json").exists());
+}
+
+#[test]
+fn compare_reports_the_differences_of_two_trees() {
+    let tree = TempTree::new();
+    tree.file("a/same.txt", "same")
+        .file("a/edited.txt", "old")
+        .file("a/removed.txt", "gone")
+        .file("b/same.txt", "same")
+        .file("b/edited.txt", "new")
+        .file("b/added.txt", "added");
+
+    let output = kushn(&tree, &["compare", "a", "b"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        "+ added.txt\n- removed.txt\n~ edited.txt\n1 added, 1 removed, 1 changed.\n"
+    );
+
+    let output = kushn(&tree, &["compare", "a", "a"]);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "0 added, 0 removed, 0 changed.\n");
+}
+
+#[test]
+fn self_entries_of_older_manifests_are_matched_by_their_normalized_path() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a");
+    assert!(kushn(&tree, &["--name", "out/./m.json"]).status.success());
+    let manifest = fs::read_to_string(tree.path().join("out/m.json")).unwrap();
+    assert!(!manifest.contains("m.json"), "{}", manifest);
+
+    // Older versions listed the manifest itself, under a `/`-separated path.
+    let listed = manifest.replacen('[', "[{\"path\":\"out/m.json\",\"hash\":\"00\"},", 1);
+    fs::write(tree.path().join("out/m.json"), listed).unwrap();
+    let output = kushn(&tree, &["--verify", "./out/m.json"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(stdout(&output).contains("1 matched, 0 modified, 0 missing, 0 added."));
+}
+
+#[test]
+fn fully_ignored_tree_needs_allow_empty() {
+    let tree = TempTree::new();
+    tree.file("a.log", "a").file(".kushnignore", "*\n");
+
+    let output = kushn(&tree, &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No files found to hash"));
+    assert!(!tree.path().join("kushn_result.json").exists());
+
+    let output = kushn(&tree, &["--allow-empty"]);
+    assert!(output.status.success());
+    let manifest = fs::read_to_string(tree.path().join("kushn_result.json")).unwrap();
+    assert_eq!(manifest.trim(), "[]");
+}
+
+#[test]
+fn sse_output_is_a_well_formed_event_stream() {
+    let tree = TempTree::new();
+    for index in 0..150 {
+        tree.file(&format!("{}.txt", index), "x");
+    }
+    let output = kushn(&tree, &["--sse"]);
+    assert!(output.status.success());
+    let stdout = stdout(&output);
+    let body = stdout.strip_suffix("\n\n").unwrap();
+
+    let mut events = Vec::new();
+    for block in body.split("\n\n") {
+        let (event, data) = block.split_once('\n').unwrap();
+        let event = event.strip_prefix("event: ").unwrap();
+        let data: serde_json::Value =
+            serde_json::from_str(data.strip_prefix("data: ").unwrap()).unwrap();
+        assert!(data.is_object(), "{}", block);
+        events.push((event.to_owned(), data));
+    }
+    let count = |name: &str| events.iter().filter(|(event, _)| event == name).count();
+    assert_eq!((count("file"), count("progress")), (150, 1));
+    let (last, done) = events.last().unwrap();
+    assert_eq!(last, "done");
+    assert_eq!(done["hashed"], 150);
+    assert_eq!(done["root_hash"].as_str().unwrap().len(), 64);
+    assert!(!tree.path().join("kushn_result.json").exists());
+}
+
+#[test]
+fn reproducible_runs_write_identical_manifests() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a").file("sub/b.txt", "b");
+    #[cfg(unix)]
+    tree.symlink("link", "sub/b.txt");
+    let run = |extra: &[&str]| {
+        let mut args = vec!["--file-metadata", "--hash-symlinks", "--name", "m.json"];
+        args.extend(extra);
+        assert!(kushn(&tree, &args).status.success());
+        fs::read(tree.path().join("m.json")).unwrap()
+    };
+    let touch = |seconds| {
+        let file = fs::File::options()
+            .write(true)
+            .open(tree.path().join("a.txt"))
+            .unwrap();
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(seconds);
+        file.set_modified(modified).unwrap();
+    };
+
+    touch(1_000_000);
+    let first = run(&[]);
+    touch(2_000_000);
+    assert_ne!(run(&[]), first);
+
+    let first = run(&["--reproducible"]);
+    touch(1_000_000);
+    let second = run(&["--reproducible"]);
+    assert_eq!(second, first);
+    let manifest = String::from_utf8_lossy(&second);
+    assert!(!manifest.contains("\"modified\""), "{}", manifest);
+    assert!(manifest.contains("\"size\": 1"), "{}", manifest);
+    #[cfg(unix)]
+    assert!(
+        manifest.contains("\"link_target\": \"sub/b.txt\""),
+        "{}",
+        manifest
+    );
+}
+
+#[test]
+fn stdin_as_records_the_piped_bytes_under_the_logical_path() {
+    let tree = TempTree::new();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_kushn"))
+        .args(["--stdin-as", "./dist/./app.bin"])
+        .current_dir(tree.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let contents = b"generated \0 artifact bytes";
+    child.stdin.take().unwrap().write_all(contents).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let entries: Vec<FileHash> = serde_json::from_slice(&output.stdout).unwrap();
+    let hash = calculate_hash_reader(&contents[..], HashAlgorithm::Sha256).unwrap();
+    assert_eq!(entries, [FileHash::new("dist/app.bin".to_owned(), hash)]);
+}
+
+#[test]
+fn printed_manifest_hash_matches_the_written_file() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a");
+    for format in ["json", "wrapped", "csv"] {
+        let output = kushn(&tree, &["--format", format, "--name", "m.out"]);
+        assert!(output.status.success());
+        let stdout = stdout(&output);
+        let printed = stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("Manifest hash: "))
+            .unwrap();
+        let written = calculate_file_hash(tree.path().join("m.out")).unwrap();
+        assert_eq!(printed, written, "{}", format);
+    }
+}
+
+#[test]
+fn base64_digests_carry_the_hex_digest_bytes() {
+    use base64::Engine as _;
+
+    let tree = TempTree::new();
+    tree.file("a.txt", "a")
+        .file("sub/b.txt", "b")
+        .file(".kushnignore", "*.json\n");
+    let manifest = |args: &[&str]| -> Vec<FileHash> {
+        assert!(kushn(&tree, args).status.success());
+        serde_json::from_slice(&fs::read(tree.path().join(args[args.len() - 1])).unwrap()).unwrap()
+    };
+    let hex = manifest(&["--name", "hex.json"]);
+    let base64 = manifest(&["--encoding", "base64", "--name", "base64.json"]);
+
+    assert_eq!(hex.len(), 3);
+    for (hex, base64) in hex.iter().zip(&base64) {
+        assert_eq!(hex.path, base64.path);
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(&base64.hash)
+            .unwrap();
+        let hex_bytes: Vec<u8> = (0..hex.hash.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex.hash[index..index + 2], 16).unwrap())
+            .collect();
+        assert_eq!(decoded, hex_bytes);
+    }
+}
+
+#[test]
+fn second_run_does_not_hash_the_previous_manifest() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a");
+    let entries = |name: &str| -> Vec<FileHash> {
+        serde_json::from_slice(&fs::read(tree.path().join(name)).unwrap()).unwrap()
+    };
+    assert!(kushn(&tree, &[]).status.success());
+    let first = entries("kushn_result.json");
+    assert!(kushn(&tree, &[]).status.success());
+    assert_eq!(entries("kushn_result.json"), first);
+    assert_eq!(first.len(), 1);
+
+    let outputs = ["--output", "m.json", "--output", "m.sfv"];
+    for _ in 0..2 {
+        assert!(kushn(&tree, &outputs).status.success());
+    }
+    let paths: Vec<String> = entries("m.json")
+        .into_iter()
+        .map(|entry| entry.path)
+        .collect();
+    assert_eq!(paths, ["a.txt", "kushn_result.json"]);
+}
+
+#[test]
+fn one_run_writes_every_output_in_its_format() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a").file("sub/b.txt", "b");
+    let output = kushn(
+        &tree,
+        &[
+            "--output",
+            "out/m.json",
+            "--output",
+            "out/m.sfv",
+            "--output",
+            "m.csv",
+        ],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+
+    let read = |name: &str| fs::read_to_string(tree.path().join(name)).unwrap();
+    let entries: Vec<FileHash> = serde_json::from_str(&read("out/m.json")).unwrap();
+    let hash = |path: &str| calculate_file_hash(tree.path().join(path)).unwrap();
+    assert_eq!(
+        entries,
+        [
+            FileHash::new("a.txt".to_owned(), hash("a.txt")),
+            FileHash::new("sub/b.txt".to_owned(), hash("sub/b.txt")),
+        ]
+    );
+    assert_eq!(
+        read("out/m.sfv"),
+        format!(
+            "{}  a.txt\n{}  sub/b.txt\n",
+            hash("a.txt"),
+            hash("sub/b.txt")
+        )
+    );
+    assert_eq!(
+        read("m.csv"),
+        format!(
+            "hash,path\n{},a.txt\n{},sub/b.txt\n",
+            hash("a.txt"),
+            hash("sub/b.txt")
+        )
+    );
+    assert!(!tree.path().join("kushn_result.json").exists());
+
+    let output = kushn(&tree, &["--output", "m.txt"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = kushn(&tree, &["--name", "n.json", "--output", "m.sfv"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--name cannot be combined"));
+    assert!(!tree.path().join("n.json").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn threaded_runs_record_links_and_report_loops() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a")
+        .dir("sub")
+        .symlink("l", "a.txt")
+        .symlink("sub/loop", "..");
+    let manifest = |args: &[&str]| {
+        let output = kushn(&tree, args);
+        assert!(output.status.success());
+        (
+            fs::read(tree.path().join("m.json")).unwrap(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    };
+    let (serial, _) = manifest(&["--hash-symlinks", "--name", "m.json"]);
+    let (threaded, _) = manifest(&["--hash-symlinks", "--threads-io", "2", "--name", "m.json"]);
+    assert_eq!(threaded, serial);
+    assert!(String::from_utf8_lossy(&threaded).contains("\"link_target\": \"a.txt\""));
+
+    let (_, stderr) = manifest(&["--follow-symlinks", "--threads-io", "2", "--name", "m.json"]);
+    assert!(stderr.contains("Warning: "), "{}", stderr);
+}
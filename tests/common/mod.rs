@@ -0,0 +1,92 @@
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A fresh directory under the system temp dir, removed again on drop.
+pub struct TempTree {
+    root: PathBuf,
+    denied: RefCell<Vec<PathBuf>>,
+}
+
+impl TempTree {
+    pub fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let root = std::env::temp_dir().join(format!(
+            "kushn-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        TempTree {
+            root,
+            denied: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    /// Writes `contents` to `relative`, creating its parent directories.
+    pub fn file(&self, relative: &str, contents: &str) -> &Self {
+        let path = self.root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+        self
+    }
+
+    pub fn dir(&self, relative: &str) -> &Self {
+        fs::create_dir_all(self.root.join(relative)).unwrap();
+        self
+    }
+}
+
+#[cfg(unix)]
+impl TempTree {
+    pub fn symlink(&self, relative: &str, target: &str) -> &Self {
+        std::os::unix::fs::symlink(target, self.root.join(relative)).unwrap();
+        self
+    }
+
+    /// Takes away all permissions on `relative`. Returns whether that denies
+    /// access, which it does not for root.
+    pub fn deny(&self, relative: &str) -> bool {
+        use std::os::unix::fs::PermissionsExt;
+        let path = self.root.join(relative);
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+        self.denied.borrow_mut().push(path.clone());
+        if path.is_dir() {
+            fs::read_dir(&path).is_err()
+        } else {
+            fs::File::open(&path).is_err()
+        }
+    }
+
+    pub fn fifo(&self, relative: &str) -> &Self {
+        let status = std::process::Command::new("mkfifo")
+            .arg(self.root.join(relative))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        self
+    }
+}
+
+impl Drop for TempTree {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        for path in self.denied.borrow().iter() {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = fs::set_permissions(path, fs::Permissions::from_mode(0o755));
+        }
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+pub fn paths(entries: &[kushn::FileHash]) -> Vec<&str> {
+    entries.iter().map(|entry| entry.path.as_str()).collect()
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use common::TempTree;
+use kushn::{content_difference, symmetric_difference, FileHash, ProcessOptions};
+
+#[test]
+fn content_difference_lists_content_missing_from_the_other_tree() {
+    let a = TempTree::new();
+    a.file("same.txt", "shared")
+        .file("moved/here.txt", "moved")
+        .file("only.txt", "unique");
+    let b = TempTree::new();
+    b.file("same.txt", "shared").file("elsewhere.txt", "moved");
+
+    let unique = content_difference(a.path(), b.path(), &ProcessOptions::new()).unwrap();
+    assert_eq!(unique, ["only.txt"]);
+}
+
+#[test]
+fn symmetric_difference_keeps_entries_unique_to_one_side() {
+    let entry = |path: &str, hash: &str| FileHash::new(path.to_owned(), hash.to_owned());
+    let a = [
+        entry("same", "1"),
+        entry("edited", "2"),
+        entry("only_a", "3"),
+    ];
+    let b = [
+        entry("same", "1"),
+        entry("edited", "4"),
+        entry("only_b", "5"),
+    ];
+    assert_eq!(
+        symmetric_difference(&a, &b),
+        [
+            entry("edited", "2"),
+            entry("edited", "4"),
+            entry("only_a", "3"),
+            entry("only_b", "5"),
+        ]
+    );
+    assert!(symmetric_difference(&a, &a).is_empty());
+}
@@ -0,0 +1,18 @@
+mod common;
+
+use common::TempTree;
+use kushn::{find_copies_of, ProcessOptions};
+
+#[test]
+fn copies_of_a_reference_file_are_found() {
+    let tree = TempTree::new();
+    tree.file("logo.png", "logo bytes")
+        .file("assets/logo-copy.png", "logo bytes")
+        .file("deep/er/logo.png", "logo bytes")
+        .file("other.png", "logo BYTES")
+        .file("longer.png", "logo bytes, but more");
+
+    let reference = tree.path().join("logo.png");
+    let copies = find_copies_of(tree.path(), &reference, &ProcessOptions::new()).unwrap();
+    assert_eq!(copies, ["assets/logo-copy.png", "deep/er/logo.png"]);
+}
@@ -0,0 +1,15 @@
+use kushn::memorable_fingerprint;
+
+#[test]
+fn fingerprints_are_deterministic() {
+    let root_hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+    let phrase = memorable_fingerprint(root_hash);
+    assert_eq!(memorable_fingerprint(root_hash), phrase);
+
+    let parts: Vec<&str> = phrase.split('-').collect();
+    assert_eq!(parts.len(), 3, "{}", phrase);
+    assert!(parts[2].len() == 4 && parts[2].chars().all(|c| c.is_ascii_digit()));
+
+    let other = "0".repeat(64);
+    assert_ne!(memorable_fingerprint(&other), phrase);
+}
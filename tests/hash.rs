@@ -0,0 +1,76 @@
+mod common;
+
+use common::TempTree;
+use kushn::{
+    calculate_file_hash, calculate_file_hash_mmap, calculate_file_hash_tapped,
+    calculate_file_hash_with, calculate_hash_reader, HashAlgorithm,
+};
+
+#[test]
+fn tap_sees_every_byte_once() {
+    let tree = TempTree::new();
+    let contents = "line\n".repeat(30_000);
+    tree.file("big.txt", &contents);
+    let path = tree.path().join("big.txt");
+
+    let mut bytes = 0;
+    let mut lines = 0;
+    let hash = calculate_file_hash_tapped(&path, HashAlgorithm::Sha256, |chunk| {
+        bytes += chunk.len();
+        lines += chunk.iter().filter(|&&byte| byte == b'\n').count();
+    })
+    .unwrap();
+    assert_eq!((bytes, lines), (contents.len(), 30_000));
+    assert_eq!(
+        hash,
+        calculate_file_hash_with(&path, HashAlgorithm::Sha256).unwrap()
+    );
+}
+
+#[test]
+fn mapped_and_streamed_digests_agree() {
+    let tree = TempTree::new();
+    tree.file("empty", "")
+        .file("small.txt", "small")
+        .file("large.bin", &"0123456789abcdef".repeat(200_000));
+    for name in ["empty", "small.txt", "large.bin"] {
+        let path = tree.path().join(name);
+        assert_eq!(
+            calculate_file_hash_mmap(&path).unwrap(),
+            calculate_file_hash(&path).unwrap(),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn digest_lengths_depend_on_the_algorithm() {
+    for algorithm in HashAlgorithm::ALL {
+        let expected = match algorithm {
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 64,
+            HashAlgorithm::Sha512 => 128,
+            HashAlgorithm::Sha1 => 40,
+            HashAlgorithm::Md5 => 32,
+            HashAlgorithm::Crc32 => 8,
+            HashAlgorithm::XxHash64 => 16,
+        };
+        let hash = calculate_hash_reader(&b"abc"[..], algorithm).unwrap();
+        assert_eq!(hash.len(), expected, "{}", algorithm);
+        assert!(hash
+            .chars()
+            .all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase()));
+    }
+
+    let abc = |algorithm| calculate_hash_reader(&b"abc"[..], algorithm).unwrap();
+    assert_eq!(
+        abc(HashAlgorithm::Sha256),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        abc(HashAlgorithm::Sha1),
+        "a9993e364706816aba3e25717850c26c9cd0d89d"
+    );
+    assert_eq!(abc(HashAlgorithm::Md5), "900150983cd24fb0d6963f7d28e17f72");
+    assert_eq!(abc(HashAlgorithm::Crc32), "352441c2");
+}
@@ -0,0 +1,45 @@
+#![cfg(feature = "http")]
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+use common::TempTree;
+use kushn::{
+    process_directory_with, serialize_entries, verify_against_url, OutputFormat, ProcessOptions,
+};
+
+/// Serves `body` to a single request and returns the URL to fetch it from.
+fn serve_once(body: String) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0u8; 4096];
+        let _ = stream.read(&mut request);
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+    format!("http://{}/kushn_result.json", address)
+}
+
+#[test]
+fn verifies_against_a_served_wrapped_manifest() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a").file("sub/b.txt", "b");
+    let options = ProcessOptions::new();
+    let manifest = process_directory_with(tree.path(), &options).unwrap();
+    tree.file("sub/b.txt", "changed");
+
+    let body = serialize_entries(&manifest, OutputFormat::Wrapped).unwrap();
+    let report = verify_against_url(tree.path(), &serve_once(body), &options).unwrap();
+    assert_eq!(report.matched, ["a.txt"]);
+    assert_eq!(report.changed, ["sub/b.txt"]);
+    assert!(report.warnings.is_empty());
+}
@@ -0,0 +1,409 @@
+use kushn::{
+    build_manifest, calculate_hash_reader, estimate_manifest_bytes, from_nested_json,
+    make_reproducible, parse_manifest, serialize_entries, strip_components, summarize_manifest,
+    to_cyclonedx, to_nested_json, to_ordered_map, to_stable_json, to_tree_string,
+    write_json_entries, FileHash, HashAlgorithm, KushnError, OutputFormat, SymlinkPolicy, Warning,
+    CYCLONEDX_SPEC_VERSION, MANIFEST_SCHEMA_VERSION,
+};
+
+fn entries() -> Vec<FileHash> {
+    vec![
+        FileHash::new("a.txt".to_owned(), "aa".to_owned()),
+        FileHash::new("sub/b.txt".to_owned(), "bb".to_owned()),
+    ]
+}
+
+#[test]
+fn json_layouts_parse_back() {
+    for format in [
+        OutputFormat::Json,
+        OutputFormat::Nested,
+        OutputFormat::Wrapped,
+    ] {
+        let output = serialize_entries(&entries(), format).unwrap();
+        let (parsed, warnings) = parse_manifest(&output, "manifest.json").unwrap();
+        assert_eq!(parsed, entries(), "{:?}", format);
+        assert!(warnings.is_empty());
+    }
+}
+
+#[test]
+fn wrapped_manifest_carries_the_schema_version() {
+    let output = serialize_entries(&entries(), OutputFormat::Wrapped).unwrap();
+    assert!(output.contains(&format!("\"schema_version\": {}", MANIFEST_SCHEMA_VERSION)));
+}
+
+#[test]
+fn wrapped_algorithm_applies_to_its_entries() {
+    let output = r#"{"$schema": "urn:kushn:manifest", "schema_version": 1, "algorithm": "blake3",
+        "files": [{"path": "a.txt", "hash": "aa"}, {"path": "b.txt", "hash": "bb"}]}"#;
+    let (parsed, _) = parse_manifest(output, "manifest.json").unwrap();
+    assert!(parsed
+        .iter()
+        .all(|entry| entry.algorithm == Some(HashAlgorithm::Blake3)));
+}
+
+#[test]
+fn other_schema_versions_still_load() {
+    let manifest = |version: u32| {
+        format!(
+            r#"{{"$schema": "urn:kushn:manifest", "schema_version": {}, "files": [{{"path": "a.txt", "hash": "aa"}}]}}"#,
+            version
+        )
+    };
+    let (parsed, warnings) = parse_manifest(&manifest(0), "old.json").unwrap();
+    assert_eq!(parsed.len(), 1);
+    assert!(warnings.is_empty());
+
+    let future = MANIFEST_SCHEMA_VERSION + 1;
+    let (parsed, warnings) = parse_manifest(&manifest(future), "new.json").unwrap();
+    assert_eq!(parsed.len(), 1);
+    assert_eq!(
+        warnings,
+        [Warning::UnknownSchemaVersion("new.json".into(), future)]
+    );
+}
+
+fn entries_with_dir() -> Vec<FileHash> {
+    let mut entries = entries();
+    entries.push(FileHash {
+        is_dir: true,
+        ..FileHash::new("sub/empty".to_owned(), String::new())
+    });
+    entries
+}
+
+#[test]
+fn empty_dirs_survive_json_round_trips() {
+    for format in [
+        OutputFormat::Json,
+        OutputFormat::Nested,
+        OutputFormat::Wrapped,
+    ] {
+        let output = serialize_entries(&entries_with_dir(), format).unwrap();
+        let (parsed, _) = parse_manifest(&output, "manifest.json").unwrap();
+        assert_eq!(parsed, entries_with_dir(), "{:?}", format);
+    }
+}
+
+#[test]
+fn estimates_cover_empty_dirs() {
+    let mut entries = entries_with_dir();
+    for index in 0..20 {
+        entries.push(FileHash {
+            is_dir: true,
+            ..FileHash::new(format!("dirs/{}", index), String::new())
+        });
+    }
+    for format in [
+        OutputFormat::Json,
+        OutputFormat::Nested,
+        OutputFormat::Wrapped,
+        OutputFormat::Csv,
+        OutputFormat::Sfv,
+    ] {
+        let actual = serialize_entries(&entries, format).unwrap().len();
+        assert!(
+            estimate_manifest_bytes(&entries, format) >= actual,
+            "{:?}",
+            format
+        );
+    }
+}
+
+#[test]
+fn summaries_do_not_count_empty_dirs() {
+    let summary = summarize_manifest(&entries_with_dir());
+    assert_eq!(summary.file_count, 2);
+    assert_eq!(summary.unique_hashes, 2);
+    assert_eq!(summary.duplicate_count, 0);
+}
+
+#[test]
+fn tree_shows_empty_dirs_as_folders() {
+    assert_eq!(
+        to_tree_string(&entries_with_dir()),
+        "a.txt  aa\nsub/\n  b.txt  bb\n  empty/\n"
+    );
+}
+
+#[test]
+fn reproducible_entries_drop_host_specific_fields() {
+    let mut entries = vec![
+        FileHash {
+            link_target: Some("/home/user/target".to_owned()),
+            uid: Some(1000),
+            gid: Some(1000),
+            modified: Some(std::time::SystemTime::now()),
+            size: Some(5),
+            ..FileHash::new("b.txt".to_owned(), "bb".to_owned())
+        },
+        FileHash::new("a.txt".to_owned(), "aa".to_owned()),
+    ];
+    let mut recorded = entries.clone();
+    make_reproducible(&mut entries, SymlinkPolicy::FollowAndRecord);
+    assert_eq!(
+        entries,
+        [
+            FileHash::new("a.txt".to_owned(), "aa".to_owned()),
+            FileHash {
+                size: Some(5),
+                ..FileHash::new("b.txt".to_owned(), "bb".to_owned())
+            },
+        ]
+    );
+
+    // The target of a link hashed as such is what the link contains.
+    make_reproducible(&mut recorded, SymlinkPolicy::HashLinkTarget);
+    assert_eq!(
+        recorded[1].link_target.as_deref(),
+        Some("/home/user/target")
+    );
+    assert_eq!((recorded[1].uid, recorded[1].modified), (None, None));
+}
+
+#[test]
+fn adding_a_file_only_adds_its_lines() {
+    let before = to_stable_json(&entries()).unwrap();
+    let mut grown = entries();
+    grown.insert(0, FileHash::new("m.txt".to_owned(), "mm".to_owned()));
+    let after = to_stable_json(&grown).unwrap();
+
+    let before: Vec<_> = before.lines().collect();
+    let after: Vec<_> = after.lines().collect();
+    let prefix = before
+        .iter()
+        .zip(&after)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    assert_eq!(prefix + suffix, before.len());
+    assert!(after.len() - before.len() <= 4, "{:#?}", after);
+}
+
+#[test]
+fn tree_indents_nested_paths_with_short_hashes() {
+    let entries = [
+        FileHash::new("src/lib.rs".to_owned(), "1".repeat(64)),
+        FileHash::new("src/bin/main.rs".to_owned(), "2".repeat(64)),
+        FileHash::new("README".to_owned(), "3".repeat(64)),
+    ];
+    assert_eq!(
+        to_tree_string(&entries),
+        "README  33333333\nsrc/\n  bin/\n    main.rs  22222222\n  lib.rs  11111111\n"
+    );
+}
+
+#[test]
+fn estimates_are_close_upper_bounds() {
+    let entries: Vec<FileHash> = (0..50)
+        .map(|index| FileHash {
+            content_type: Some("text/plain".to_owned()),
+            size: Some(index * 1000),
+            algorithm: Some(HashAlgorithm::Blake3),
+            ..FileHash::new(
+                format!("dir{}/file{}.txt", index % 7, index),
+                "ab".repeat(32),
+            )
+        })
+        .collect();
+    for format in [
+        OutputFormat::Json,
+        OutputFormat::Nested,
+        OutputFormat::Wrapped,
+        OutputFormat::Csv,
+        OutputFormat::Sfv,
+    ] {
+        let actual = serialize_entries(&entries, format).unwrap().len();
+        let estimate = estimate_manifest_bytes(&entries, format);
+        assert!(
+            estimate >= actual && estimate <= actual + actual / 4,
+            "{:?}: estimated {} for {}",
+            format,
+            estimate,
+            actual
+        );
+    }
+}
+
+#[test]
+fn summaries_count_duplicates() {
+    let sized = |path: &str, hash: &str, size| FileHash {
+        size: Some(size),
+        ..FileHash::new(path.to_owned(), hash.to_owned())
+    };
+    let mut entries = vec![
+        sized("a", "1", 10),
+        sized("copy_of_a", "1", 10),
+        sized("b", "2", 5),
+    ];
+    let summary = summarize_manifest(&entries);
+    assert_eq!(
+        (
+            summary.file_count,
+            summary.unique_hashes,
+            summary.duplicate_count
+        ),
+        (3, 2, 1)
+    );
+    assert_eq!(summary.total_bytes, Some(25));
+
+    entries[2].size = None;
+    assert_eq!(summarize_manifest(&entries).total_bytes, None);
+}
+
+#[test]
+fn nested_json_round_trips_a_multi_level_tree() {
+    let entries = [
+        FileHash::new("README".to_owned(), "r".to_owned()),
+        FileHash::new("src/bin/main.rs".to_owned(), "m".to_owned()),
+        FileHash::new("src/lib.rs".to_owned(), "l".to_owned()),
+        FileHash::new("src/util/deep/x.rs".to_owned(), "x".to_owned()),
+    ];
+    let nested = to_nested_json(&entries);
+    assert_eq!(
+        nested,
+        serde_json::json!({
+            "README": "r",
+            "src": {
+                "bin": { "main.rs": "m" },
+                "lib.rs": "l",
+                "util": { "deep": { "x.rs": "x" } },
+            },
+        })
+    );
+    assert_eq!(from_nested_json(&nested).unwrap(), entries);
+}
+
+#[test]
+fn ordered_maps_sort_by_path_and_reject_duplicates() {
+    let mut entries = vec![
+        FileHash::new("z.txt".to_owned(), "z".to_owned()),
+        FileHash::new("a/b.txt".to_owned(), "b".to_owned()),
+        FileHash::new("a.txt".to_owned(), "a".to_owned()),
+    ];
+    let map = to_ordered_map(&entries).unwrap();
+    assert_eq!(
+        map.keys().map(String::as_str).collect::<Vec<_>>(),
+        ["a.txt", "a/b.txt", "z.txt"]
+    );
+    assert_eq!(map["a/b.txt"], entries[1]);
+
+    entries.push(FileHash::new("a.txt".to_owned(), "again".to_owned()));
+    assert!(matches!(
+        to_ordered_map(&entries),
+        Err(KushnError::DuplicatePath(path)) if path == "a.txt"
+    ));
+}
+
+#[test]
+fn cyclonedx_lists_a_component_per_file() {
+    let mut entries = entries();
+    entries.push(FileHash {
+        algorithm: Some(HashAlgorithm::Crc32),
+        ..FileHash::new("c.bin".to_owned(), "cc".to_owned())
+    });
+    let sbom = to_cyclonedx(&entries);
+    assert_eq!(sbom["bomFormat"], "CycloneDX");
+    assert_eq!(sbom["specVersion"], CYCLONEDX_SPEC_VERSION);
+
+    let components = sbom["components"].as_array().unwrap();
+    assert_eq!(components.len(), 3);
+    assert_eq!(components[1]["type"], "file");
+    assert_eq!(components[1]["name"], "sub/b.txt");
+    assert_eq!(
+        components[1]["hashes"],
+        serde_json::json!([{ "alg": "SHA-256", "content": "bb" }])
+    );
+    assert_eq!(components[2]["hashes"], serde_json::json!([]));
+}
+
+#[test]
+fn built_manifests_come_with_the_hash_of_their_bytes() {
+    for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+        let (output, entry) =
+            build_manifest(&entries(), OutputFormat::Wrapped, "./out/m.json", algorithm).unwrap();
+        assert_eq!(entry.path, "out/m.json");
+        assert_eq!(
+            entry.hash,
+            calculate_hash_reader(output.as_bytes(), algorithm).unwrap()
+        );
+        assert_eq!(
+            output,
+            serialize_entries(&entries(), OutputFormat::Wrapped).unwrap()
+        );
+    }
+}
+
+#[test]
+fn strip_components_drops_leading_directories() {
+    let paths = |entries: &[FileHash]| -> Vec<String> {
+        entries.iter().map(|entry| entry.path.clone()).collect()
+    };
+    let build = || {
+        vec![
+            FileHash::new("build/out/src/lib.rs".to_owned(), "l".to_owned()),
+            FileHash::new("build/out/README".to_owned(), "r".to_owned()),
+            FileHash::new("build/VERSION".to_owned(), "v".to_owned()),
+        ]
+    };
+    for (count, expected) in [
+        (
+            0,
+            vec!["build/out/src/lib.rs", "build/out/README", "build/VERSION"],
+        ),
+        (1, vec!["out/src/lib.rs", "out/README", "VERSION"]),
+        (2, vec!["src/lib.rs", "README"]),
+        (3, vec!["lib.rs"]),
+        (4, vec![]),
+    ] {
+        let mut entries = build();
+        strip_components(&mut entries, count, false).unwrap();
+        assert_eq!(paths(&entries), expected, "{}", count);
+    }
+
+    let mut entries = build();
+    assert!(strip_components(&mut entries, 1, true).is_ok());
+    let mut entries = build();
+    assert!(matches!(
+        strip_components(&mut entries, 2, true),
+        Err(KushnError::TooFewComponents { path, count: 2 }) if path == "build/VERSION"
+    ));
+    assert_eq!(entries, build());
+}
+
+#[test]
+fn streamed_json_matches_the_pretty_output() {
+    let detailed = FileHash {
+        link_target: Some("../target".to_owned()),
+        content_type: Some("text/plain".to_owned()),
+        uid: Some(1000),
+        gid: Some(100),
+        algorithm: Some(HashAlgorithm::Blake3),
+        size: Some(42),
+        modified: Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000)),
+        ..FileHash::new("sub/with \"quotes\" and \\.txt".to_owned(), "cc".to_owned())
+    };
+    let empty_dir = FileHash {
+        is_dir: true,
+        ..FileHash::new("line\nbreak/\u{7}bell/ünï".to_owned(), String::new())
+    };
+    let several = [entries(), vec![detailed, empty_dir]].concat();
+    for entries in [Vec::new(), entries()[..1].to_vec(), several] {
+        let mut streamed = Vec::new();
+        write_json_entries(&mut streamed, &entries).unwrap();
+        assert_eq!(
+            String::from_utf8(streamed).unwrap(),
+            serde_json::to_string_pretty(&entries).unwrap()
+        );
+        assert_eq!(
+            serialize_entries(&entries, OutputFormat::Json).unwrap(),
+            serde_json::to_string_pretty(&entries).unwrap()
+        );
+    }
+}
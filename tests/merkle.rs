@@ -0,0 +1,46 @@
+use kushn::{merkle_proof, merkle_root, verify_merkle_proof, FileHash, MerkleTree};
+
+fn entries() -> Vec<FileHash> {
+    (0..5)
+        .map(|index| FileHash::new(format!("file{}.txt", index), format!("{:064x}", index)))
+        .collect()
+}
+
+#[test]
+fn proofs_verify_against_the_root() {
+    let entries = entries();
+    let root = merkle_root(&entries);
+    for entry in &entries {
+        let proof = merkle_proof(&entries, &entry.path).unwrap();
+        assert!(verify_merkle_proof(entry, &proof, &root), "{}", entry.path);
+    }
+
+    let proof = merkle_proof(&entries, "file2.txt").unwrap();
+    let tampered = FileHash::new("file2.txt".to_owned(), format!("{:064x}", 9));
+    assert!(!verify_merkle_proof(&tampered, &proof, &root));
+    assert!(merkle_proof(&entries, "missing.txt").is_none());
+}
+
+#[test]
+fn incremental_updates_match_a_full_recompute() {
+    let mut entries = entries();
+    let mut tree = MerkleTree::new(&entries);
+    assert_eq!(tree.root(), merkle_root(&entries));
+
+    let changes = [
+        ("file1.txt".to_owned(), Some("ff".repeat(32))),
+        ("file3.txt".to_owned(), Some("ee".repeat(32))),
+    ];
+    entries[1].hash = "ff".repeat(32);
+    entries[3].hash = "ee".repeat(32);
+    assert_eq!(tree.update(&changes), merkle_root(&entries));
+
+    let changes = [
+        ("file0.txt".to_owned(), None),
+        ("new.txt".to_owned(), Some("dd".repeat(32))),
+    ];
+    entries.remove(0);
+    entries.push(FileHash::new("new.txt".to_owned(), "dd".repeat(32)));
+    assert_eq!(tree.update(&changes), merkle_root(&entries));
+    assert_eq!(tree.len(), entries.len());
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::{paths, TempTree};
+use kushn::{calculate_file_hash, hash_files_parallel, process_directory_with, ProcessOptions};
+
+// Changes the working directory, which no other test in this binary relies on.
+#[test]
+fn explicit_files_are_recorded_like_scanned_ones() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "alpha").file("deep/er/z.txt", "zed");
+    std::env::set_current_dir(tree.path()).unwrap();
+
+    let files = [PathBuf::from("./a.txt"), PathBuf::from("deep/./er/z.txt")];
+    let options = ProcessOptions::new();
+    let hashed: Vec<_> = hash_files_parallel(&files, &options)
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(paths(&hashed), ["a.txt", "deep/er/z.txt"]);
+    assert_eq!(hashed, process_directory_with(".", &options).unwrap());
+}
+
+#[test]
+fn results_keep_the_input_order() {
+    let tree = TempTree::new();
+    let files: Vec<PathBuf> = (0..50)
+        .rev()
+        .map(|index| {
+            let name = format!("f{}.txt", index);
+            tree.file(&name, &index.to_string());
+            tree.path().join(name)
+        })
+        .collect();
+    let options = ProcessOptions::new().with_threads(Some(4));
+    let results = hash_files_parallel(&files, &options);
+    assert_eq!(results.len(), files.len());
+    for (file, result) in files.iter().zip(results) {
+        let entry = result.unwrap();
+        assert_eq!(entry.path, file.to_str().unwrap());
+        assert_eq!(entry.hash, calculate_file_hash(file).unwrap());
+    }
+
+    let missing = [tree.path().join("missing.txt")];
+    assert!(hash_files_parallel(&missing, &options)[0].is_err());
+}
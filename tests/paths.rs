@@ -0,0 +1,39 @@
+use kushn::{to_extended_length_path, WINDOWS_MAX_PATH};
+
+#[test]
+fn long_windows_paths_get_the_extended_length_prefix() {
+    let deep = "segment\\".repeat(WINDOWS_MAX_PATH / 8 + 1);
+    let long_drive = format!("C:\\{}file.txt", deep);
+    assert!(long_drive.len() > WINDOWS_MAX_PATH);
+    assert_eq!(
+        to_extended_length_path(&long_drive),
+        format!("\\\\?\\{}", long_drive)
+    );
+    assert_eq!(
+        to_extended_length_path(&long_drive.replace('\\', "/")),
+        format!("\\\\?\\{}", long_drive)
+    );
+
+    let long_unc = format!("\\\\server\\share\\{}file.txt", deep);
+    assert_eq!(
+        to_extended_length_path(&long_unc),
+        format!("\\\\?\\UNC\\server\\share\\{}file.txt", deep)
+    );
+
+    let prefixed = format!("\\\\?\\{}", long_drive);
+    assert_eq!(to_extended_length_path(&prefixed), prefixed);
+    assert_eq!(to_extended_length_path("C:\\short.txt"), "C:\\short.txt");
+}
+
+#[test]
+fn long_relative_paths_are_returned_unchanged() {
+    let deep = "segment/".repeat(WINDOWS_MAX_PATH / 8 + 1);
+    for relative in [
+        format!("{}file.txt", deep),
+        format!(".\\{}file.txt", deep.replace('/', "\\")),
+        format!("C:{}file.txt", deep),
+    ] {
+        assert!(relative.len() > WINDOWS_MAX_PATH);
+        assert_eq!(to_extended_length_path(&relative), relative);
+    }
+}
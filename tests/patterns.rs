@@ -0,0 +1,164 @@
+mod common;
+
+use common::{paths, TempTree};
+use kushn::{
+    load_ignore_file, normalize_pattern, parse_ignore_file, process_directory_with,
+    process_file_with, KushnError, ProcessOptions,
+};
+
+#[test]
+fn windows_style_patterns_match_in_every_function() {
+    assert_eq!(normalize_pattern(r"cache\*"), "cache/*");
+    assert_eq!(normalize_pattern(r"!build\keep\"), "!build/keep/");
+
+    let tree = TempTree::new();
+    tree.file("cache/x.tmp", "x")
+        .file("deep/cache/y.tmp", "y")
+        .file("build/out.o", "o")
+        .file("src/main.rs", "fn main() {}");
+    let options = ProcessOptions::new()
+        .with_ignore(vec![r"cache\*".to_owned(), r"build\".to_owned()])
+        .with_base_dir(tree.path());
+
+    let entries = process_directory_with(tree.path(), &options).unwrap();
+    assert_eq!(paths(&entries), ["src/main.rs"]);
+    for ignored in ["cache/x.tmp", "deep/cache/y.tmp", "build/out.o"] {
+        let entry = process_file_with(tree.path().join(ignored), &options).unwrap();
+        assert_eq!(entry, None, "{}", ignored);
+    }
+    let entry = process_file_with(tree.path().join("src/main.rs"), &options).unwrap();
+    assert_eq!(entry.unwrap(), entries[0]);
+}
+
+#[test]
+fn included_ignore_files_add_their_patterns() {
+    let tree = TempTree::new();
+    tree.file(".kushnignore", "*.log\ninclude shared/base.ignore\n")
+        .file("shared/base.ignore", "*.tmp\ninclude more.ignore\n")
+        .file("shared/more.ignore", "build/\n")
+        .file("a.log", "l")
+        .file("b.tmp", "t")
+        .file("build/out.o", "o")
+        .file("src/main.rs", "m");
+    let patterns = load_ignore_file(tree.path().join(".kushnignore")).unwrap();
+    assert_eq!(patterns, ["*.log", "*.tmp", "build/"]);
+
+    let options = ProcessOptions::new().with_ignore(patterns);
+    let entries = process_directory_with(tree.path(), &options).unwrap();
+    assert_eq!(
+        paths(&entries),
+        [
+            ".kushnignore",
+            "shared/base.ignore",
+            "shared/more.ignore",
+            "src/main.rs"
+        ]
+    );
+
+    tree.file("shared/more.ignore", "include ../.kushnignore\n");
+    assert!(matches!(
+        load_ignore_file(tree.path().join(".kushnignore")),
+        Err(KushnError::IgnoreIncludeCycle { .. })
+    ));
+}
+
+#[test]
+fn negation_re_includes_files_of_an_excluded_dir() {
+    let tree = TempTree::new();
+    tree.file("target/keep.bin", "k")
+        .file("target/drop.bin", "d")
+        .file("target/debug/keep.bin", "deep")
+        .file("logs/a.log", "a")
+        .file("logs/b.log", "b");
+    let scan = |patterns: &[&str]| {
+        let options =
+            ProcessOptions::new().with_ignore(patterns.iter().map(|p| p.to_string()).collect());
+        let entries = process_directory_with(tree.path(), &options).unwrap();
+        paths(&entries)
+            .into_iter()
+            .map(str::to_owned)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        scan(&["target/", "!target/keep.bin"]),
+        ["logs/a.log", "logs/b.log", "target/keep.bin"]
+    );
+    // The last matching pattern wins.
+    assert_eq!(
+        scan(&["*.log", "!b.log", "logs/"]),
+        [
+            "target/debug/keep.bin",
+            "target/drop.bin",
+            "target/keep.bin"
+        ]
+    );
+    assert_eq!(
+        scan(&["*.log", "!b.log", "target/", "!keep.bin"]),
+        ["logs/b.log", "target/debug/keep.bin", "target/keep.bin"]
+    );
+}
+
+#[test]
+fn nested_ignore_files_apply_to_their_own_subtree() {
+    let tree = TempTree::new();
+    tree.file(".kushnignore", "*.log\n")
+        .file("sub/.kushnignore", "*.tmp\nlocal.txt\n!keep.log\n")
+        .file("a.log", "a")
+        .file("a.tmp", "a")
+        .file("local.txt", "root")
+        .file("sub/b.log", "b")
+        .file("sub/keep.log", "k")
+        .file("sub/b.tmp", "b")
+        .file("sub/local.txt", "sub")
+        .file("sub/deeper/local.txt", "deeper");
+    let options = ProcessOptions::new()
+        .with_ignore(load_ignore_file(tree.path().join(".kushnignore")).unwrap())
+        .with_nested_ignore(true);
+    let entries = process_directory_with(tree.path(), &options).unwrap();
+    assert_eq!(
+        paths(&entries),
+        [
+            ".kushnignore",
+            "a.tmp",
+            "local.txt",
+            "sub/.kushnignore",
+            "sub/keep.log"
+        ]
+    );
+}
+
+#[test]
+fn ignore_files_skip_blank_lines_and_comments() {
+    let contents = "# build output\ntarget/\n\n   \n    # indented comment\n*.log   \n\t*.tmp\r\nnot#comment\n";
+    assert_eq!(
+        parse_ignore_file(contents),
+        ["target/", "*.log", "*.tmp", "not#comment"]
+    );
+    assert!(parse_ignore_file("").is_empty());
+}
+
+#[test]
+fn ignore_case_is_opt_in() {
+    let tree = TempTree::new();
+    tree.file("Notes.TXT", "n")
+        .file("lower.txt", "l")
+        .file("Build/out.o", "o")
+        .file("keep.md", "k");
+    let options = ProcessOptions::new()
+        .with_ignore(vec!["*.txt".to_owned(), "build/".to_owned()])
+        .with_base_dir(tree.path());
+    let scan = |options: &ProcessOptions| {
+        let entries = process_directory_with(tree.path(), options).unwrap();
+        paths(&entries)
+            .into_iter()
+            .map(str::to_owned)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(scan(&options), ["Build/out.o", "Notes.TXT", "keep.md"]);
+    let insensitive = options.with_case_insensitive(true);
+    assert_eq!(scan(&insensitive), ["keep.md"]);
+    let file = tree.path().join("Notes.TXT");
+    assert_eq!(process_file_with(&file, &insensitive).unwrap(), None);
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::{paths, TempTree};
+use kushn::{process_directory_queued, process_directory_with, KushnError, ProcessOptions};
+#[cfg(unix)]
+use kushn::{scan_directory, scan_directory_queued, SymlinkPolicy};
+
+fn sample_tree() -> TempTree {
+    let tree = TempTree::new();
+    tree.file("a.txt", "alpha")
+        .file("big.bin", &"x".repeat(500))
+        .file("s/.kushnignore", "*.log\n")
+        .file("s/x.log", "log")
+        .file("s/y.txt", "why")
+        .file("deep/er/z.txt", "zed")
+        .dir("empty");
+    tree
+}
+
+fn split(options: ProcessOptions) -> ProcessOptions {
+    options.with_io_threads(Some(2)).with_hash_threads(Some(3))
+}
+
+#[test]
+fn queued_matches_serial_scan() {
+    let tree = sample_tree();
+    let configurations = [
+        ProcessOptions::new().with_nested_ignore(true),
+        ProcessOptions::new()
+            .with_nested_ignore(true)
+            .with_min_size(Some(4))
+            .with_max_size(Some(100)),
+        ProcessOptions::new()
+            .with_empty_dirs(true)
+            .with_file_metadata(true)
+            .with_ignore(vec!["deep/".to_owned()]),
+        ProcessOptions::new().with_include(vec!["*.txt".to_owned()]),
+        ProcessOptions::new()
+            .with_dot_prefix(true)
+            .with_path_binding(true),
+    ];
+    for options in configurations {
+        let serial = process_directory_with(tree.path(), &options).unwrap();
+        let queued = process_directory_queued(tree.path(), &options).unwrap();
+        assert_eq!(queued, serial);
+        let split = process_directory_queued(tree.path(), &split(options)).unwrap();
+        assert_eq!(split, serial);
+    }
+}
+
+#[test]
+fn queued_honours_nested_ignore_files() {
+    let tree = sample_tree();
+    let options = ProcessOptions::new().with_nested_ignore(true);
+    let entries = process_directory_queued(tree.path(), &split(options)).unwrap();
+    assert!(!paths(&entries).contains(&"s/x.log"));
+    assert!(paths(&entries).contains(&"s/y.txt"));
+}
+
+#[test]
+fn queued_enforces_limits() {
+    let tree = sample_tree();
+    let options = ProcessOptions::new().with_max_file_size(Some(100));
+    assert!(matches!(
+        process_directory_with(tree.path(), &options),
+        Err(KushnError::FileTooLarge { .. })
+    ));
+    assert!(matches!(
+        process_directory_queued(tree.path(), &options),
+        Err(KushnError::FileTooLarge { .. })
+    ));
+    let options = ProcessOptions::new().with_max_files(Some(2));
+    assert!(matches!(
+        process_directory_queued(tree.path(), &split(options)),
+        Err(KushnError::FileLimitExceeded { limit: 2 })
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn queued_matches_serial_scan_over_symlinks() {
+    let tree = sample_tree();
+    tree.symlink("link.txt", "a.txt")
+        .symlink("linked_dir", "deep")
+        .symlink("dangling", "missing.txt")
+        .symlink("deep/er/loop", "..");
+    for policy in [
+        SymlinkPolicy::Skip,
+        SymlinkPolicy::Follow,
+        SymlinkPolicy::FollowAndRecord,
+        SymlinkPolicy::HashLinkTarget,
+    ] {
+        let options = ProcessOptions::new()
+            .with_symlinks(policy)
+            .with_continue_on_error(true);
+        let serial = scan_directory(tree.path(), &options).unwrap();
+        for options in [options.clone(), split(options.clone())] {
+            let queued = scan_directory_queued(tree.path(), &options).unwrap();
+            assert_eq!(queued.entries, serial.entries, "{:?}", policy);
+            assert_eq!(queued.warnings, serial.warnings, "{:?}", policy);
+            assert_eq!(queued.errors.len(), serial.errors.len(), "{:?}", policy);
+        }
+        if policy == SymlinkPolicy::HashLinkTarget {
+            assert!(serial
+                .entries
+                .iter()
+                .any(|entry| entry.path == "link.txt"
+                    && entry.link_target.as_deref() == Some("a.txt")));
+        } else {
+            assert!(!serial.warnings.is_empty(), "{:?}", policy);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use std::env;
+
+use common::{paths, TempTree};
+use kushn::{process_directory_with, BaseStripping, ProcessOptions};
+
+// A single test, as it changes the working directory to scan `.`.
+#[test]
+fn entry_paths_at_the_scan_root() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a").file("sub/b.txt", "b");
+    let exclusive = ProcessOptions::new().with_allow_empty(true);
+    let inclusive = exclusive
+        .clone()
+        .with_base_stripping(BaseStripping::Inclusive);
+    let scan = |root: &str, options: &ProcessOptions| -> Vec<String> {
+        let entries = process_directory_with(root, options).unwrap();
+        paths(&entries).into_iter().map(str::to_owned).collect()
+    };
+
+    let root = tree.path().to_str().unwrap().to_owned();
+    assert_eq!(scan(&root, &exclusive), ["a.txt", "sub/b.txt"]);
+    assert_eq!(
+        scan(&format!("{}/", root), &exclusive),
+        scan(&root, &exclusive)
+    );
+    let name = tree
+        .path()
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned();
+    assert_eq!(
+        scan(&root, &inclusive),
+        [format!("{}/a.txt", name), format!("{}/sub/b.txt", name)]
+    );
+
+    // An absolute path naming a file is never an entry of its own.
+    let file = tree.path().join("a.txt");
+    let file = file.to_str().unwrap();
+    assert!(scan(file, &exclusive).is_empty());
+    assert_eq!(scan(file, &inclusive), ["a.txt"]);
+
+    env::set_current_dir(tree.path()).unwrap();
+    assert_eq!(scan(".", &exclusive), ["a.txt", "sub/b.txt"]);
+    assert_eq!(scan("./", &exclusive), ["a.txt", "sub/b.txt"]);
+    assert_eq!(scan("sub", &exclusive), ["b.txt"]);
+    assert_eq!(scan("./sub/", &inclusive), ["sub/b.txt"]);
+    assert_eq!(scan(".", &inclusive), scan(&root, &inclusive));
+}
@@ -0,0 +1,576 @@
+mod common;
+
+use std::fs;
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+use common::{paths, TempTree};
+use kushn::{
+    calculate_file_hash, parse_size, process_directory_cancellable, process_directory_filtered,
+    process_directory_queued, process_directory_timed, process_directory_with,
+    process_directory_with_progress, scan_directory, KushnError, ProcessOptions, Traversal,
+    Warning, DEFAULT_CONTENT_TYPE,
+};
+#[cfg(unix)]
+use kushn::{process_directory, process_directory_with_errors, SymlinkPolicy};
+
+#[cfg(unix)]
+#[test]
+fn hardened_skips_links_and_special_files_and_denied_dirs() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a")
+        .file("locked/secret.txt", "s")
+        .symlink("link.txt", "a.txt")
+        .fifo("pipe");
+    let denied = tree.deny("locked");
+
+    let report = scan_directory(tree.path(), &ProcessOptions::hardened()).unwrap();
+    let expected: &[&str] = if denied {
+        &["a.txt"]
+    } else {
+        &["a.txt", "locked/secret.txt"]
+    };
+    assert_eq!(paths(&report.entries), expected);
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+    let root = tree.path();
+    assert!(report
+        .warnings
+        .contains(&Warning::SkippedSymlink(root.join("link.txt"))));
+    assert!(report
+        .warnings
+        .contains(&Warning::SkippedSpecialFile(root.join("pipe"))));
+    if denied {
+        assert!(report
+            .warnings
+            .contains(&Warning::AccessDenied(root.join("locked"))));
+    }
+}
+
+#[test]
+fn path_binding_separates_identical_content() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "same").file("b.txt", "same");
+    let hashes = |options: &ProcessOptions| -> Vec<String> {
+        let report = scan_directory(tree.path(), options).unwrap();
+        report.entries.into_iter().map(|entry| entry.hash).collect()
+    };
+
+    let plain = hashes(&ProcessOptions::new());
+    assert_eq!(plain[0], plain[1]);
+    let bound = hashes(&ProcessOptions::new().with_path_binding(true));
+    assert_ne!(bound[0], bound[1]);
+    assert_ne!(bound[0], plain[0]);
+}
+
+#[cfg(unix)]
+#[test]
+fn follow_and_record_keeps_the_link_target() {
+    let tree = TempTree::new();
+    tree.file("real/a.txt", "a")
+        .symlink("link.txt", "real/a.txt");
+    let options = ProcessOptions::new().with_symlinks(SymlinkPolicy::FollowAndRecord);
+    let report = scan_directory(tree.path(), &options).unwrap();
+
+    let link = &report.entries[0];
+    let target = &report.entries[1];
+    assert_eq!(
+        (link.path.as_str(), target.path.as_str()),
+        ("link.txt", "real/a.txt")
+    );
+    assert_eq!(link.hash, target.hash);
+    let canonical = fs::canonicalize(tree.path().join("real/a.txt")).unwrap();
+    assert_eq!(link.link_target.as_deref(), canonical.to_str());
+    assert_eq!(target.link_target, None);
+}
+
+#[test]
+fn uniform_files_are_flagged() {
+    let tree = TempTree::new();
+    tree.file("zeros.bin", &"\0".repeat(100_000))
+        .file("text.txt", "not uniform");
+    let options = ProcessOptions::new().with_detect_uniform(true);
+    let report = scan_directory(tree.path(), &options).unwrap();
+    assert_eq!(report.uniform_files, ["zeros.bin"]);
+}
+
+#[test]
+fn files_over_the_size_limit_fail_the_scan() {
+    let tree = TempTree::new();
+    tree.file("small.txt", "s")
+        .file("big/blob.bin", &"x".repeat(2000));
+    let limit = parse_size("1KB").unwrap();
+    assert_eq!(limit, 1000);
+
+    let options = ProcessOptions::new().with_max_file_size(Some(limit));
+    match process_directory_with(tree.path(), &options) {
+        Err(KushnError::FileTooLarge { path, size, limit }) => {
+            assert!(path.ends_with("big/blob.bin"), "{}", path.display());
+            assert_eq!((size, limit), (2000, 1000));
+        }
+        other => panic!("{:?}", other),
+    }
+    let options = ProcessOptions::new().with_max_file_size(parse_size("2KiB").ok());
+    assert_eq!(
+        process_directory_with(tree.path(), &options).unwrap().len(),
+        2
+    );
+}
+
+#[test]
+fn content_types_are_sniffed() {
+    let tree = TempTree::new();
+    tree.file("notes.txt", "plain text");
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01\0\0\0\x01\x08\x06\0\0\0";
+    fs::write(tree.path().join("image.png"), png).unwrap();
+
+    let options = ProcessOptions::new().with_content_type(true);
+    let report = scan_directory(tree.path(), &options).unwrap();
+    let types: Vec<_> = report
+        .entries
+        .iter()
+        .map(|entry| entry.content_type.as_deref().unwrap())
+        .collect();
+    assert_eq!(types, ["image/png", DEFAULT_CONTENT_TYPE]);
+    assert_eq!(
+        scan_directory(tree.path(), &ProcessOptions::new())
+            .unwrap()
+            .entries[0]
+            .content_type,
+        None
+    );
+}
+
+#[test]
+fn traversals_give_the_same_manifest() {
+    let tree = TempTree::new();
+    tree.file("z.txt", "z")
+        .file("a/deep/er/1.txt", "1")
+        .file("a/2.txt", "2")
+        .file("b/3.txt", "3")
+        .file("b/c/4.txt", "4");
+    let scan = |traversal| {
+        let options = ProcessOptions::new().with_traversal(traversal);
+        process_directory_with(tree.path(), &options).unwrap()
+    };
+    let depth_first = scan(Traversal::DepthFirst);
+    assert_eq!(depth_first.len(), 5);
+    assert_eq!(scan(Traversal::BreadthFirst), depth_first);
+}
+
+#[test]
+fn max_age_skips_old_files() {
+    let tree = TempTree::new();
+    tree.file("old.txt", "old").file("new.txt", "new");
+    let day = Duration::from_secs(24 * 60 * 60);
+    fs::File::options()
+        .write(true)
+        .open(tree.path().join("old.txt"))
+        .unwrap()
+        .set_modified(SystemTime::now() - 2 * day)
+        .unwrap();
+
+    let options = ProcessOptions::new().with_max_age(Some(day));
+    let entries = process_directory_with(tree.path(), &options).unwrap();
+    assert_eq!(paths(&entries), ["new.txt"]);
+}
+
+#[test]
+fn samples_are_reproducible_for_a_seed() {
+    let tree = TempTree::new();
+    for index in 0..100 {
+        tree.file(&format!("{}.txt", index), "x");
+    }
+    let sample = |seed| {
+        let options = ProcessOptions::new().with_sample(0.3, seed);
+        let entries = process_directory_with(tree.path(), &options).unwrap();
+        paths(&entries)
+            .into_iter()
+            .map(str::to_owned)
+            .collect::<Vec<_>>()
+    };
+
+    let first = sample(7);
+    assert!((10..60).contains(&first.len()), "{}", first.len());
+    assert_eq!(sample(7), first);
+    assert_ne!(sample(8), first);
+}
+
+#[cfg(unix)]
+#[test]
+fn denied_dirs_and_dangling_links_become_warnings() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a")
+        .file("locked/secret.txt", "s")
+        .symlink("dangling", "missing.txt");
+    let denied = tree.deny("locked");
+
+    let options = ProcessOptions::new()
+        .with_symlinks(SymlinkPolicy::Follow)
+        .with_continue_on_error(true);
+    let report = scan_directory(tree.path(), &options).unwrap();
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+    assert!(report
+        .warnings
+        .contains(&Warning::DanglingSymlink(tree.path().join("dangling"))));
+    if denied {
+        assert!(report
+            .warnings
+            .contains(&Warning::AccessDenied(tree.path().join("locked"))));
+        assert_eq!(paths(&report.entries), ["a.txt"]);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_are_skipped_unless_followed() {
+    let tree = TempTree::new();
+    tree.file("real/a.txt", "a")
+        .symlink("link.txt", "real/a.txt")
+        .symlink("linked_dir", "real");
+
+    let default = process_directory_with(tree.path(), &ProcessOptions::new()).unwrap();
+    assert_eq!(paths(&default), ["real/a.txt"]);
+    assert_eq!(process_directory(tree.path(), &[]), default);
+
+    let options = ProcessOptions::new().with_symlinks(SymlinkPolicy::Follow);
+    let followed = process_directory_with(tree.path(), &options).unwrap();
+    assert_eq!(
+        paths(&followed),
+        ["link.txt", "linked_dir/a.txt", "real/a.txt"]
+    );
+    assert!(followed.iter().all(|entry| entry.hash == default[0].hash));
+}
+
+#[test]
+fn dot_prefix_only_changes_the_recorded_paths() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a")
+        .file("sub/b.txt", "b")
+        .file("sub/c.log", "c");
+    let options = ProcessOptions::new().with_ignore(vec!["sub/*.log".to_owned()]);
+    let plain = process_directory_with(tree.path(), &options).unwrap();
+    let prefixed = process_directory_with(tree.path(), &options.with_dot_prefix(true)).unwrap();
+    assert_eq!(paths(&prefixed), ["./a.txt", "./sub/b.txt"]);
+    for (plain, prefixed) in plain.iter().zip(&prefixed) {
+        assert_eq!(plain.hash, prefixed.hash);
+    }
+}
+
+#[test]
+fn max_dirs_aborts_larger_trees() {
+    let tree = TempTree::new();
+    tree.file("a/1.txt", "1")
+        .file("b/2.txt", "2")
+        .file("b/c/3.txt", "3");
+    // The root and three directories below it.
+    let scan = |limit| {
+        process_directory_with(
+            tree.path(),
+            &ProcessOptions::new().with_max_dirs(Some(limit)),
+        )
+    };
+    assert!(matches!(
+        scan(3),
+        Err(KushnError::DirLimitExceeded { limit: 3 })
+    ));
+    assert_eq!(scan(4).unwrap().len(), 3);
+}
+
+#[test]
+fn gzip_files_hash_as_their_decompressed_content() {
+    use std::io::Write;
+
+    let tree = TempTree::new();
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    encoder.write_all(b"logical content").unwrap();
+    fs::write(tree.path().join("data.gz"), encoder.finish().unwrap()).unwrap();
+    tree.file("plain.txt", "logical content")
+        .file("broken.gz", "not gzip");
+
+    let options = ProcessOptions::new().with_decompress_extensions(vec!["gz".to_owned()]);
+    let report = scan_directory(tree.path(), &options).unwrap();
+    let hash = |path: &str| {
+        let entry = report.entries.iter().find(|entry| entry.path == path);
+        entry.unwrap().hash.clone()
+    };
+    assert_eq!(hash("data.gz"), hash("plain.txt"));
+    assert_eq!(
+        hash("broken.gz"),
+        calculate_file_hash(tree.path().join("broken.gz")).unwrap()
+    );
+    assert_eq!(
+        report.warnings,
+        [Warning::DecompressionFailed(tree.path().join("broken.gz"))]
+    );
+}
+
+#[test]
+fn dedup_keeps_the_smallest_path_per_content() {
+    let tree = TempTree::new();
+    tree.file("z/copy.txt", "same")
+        .file("b.txt", "same")
+        .file("m.txt", "same")
+        .file("other.txt", "other");
+    let options = ProcessOptions::new().with_dedup_content(true);
+    for _ in 0..3 {
+        let entries = process_directory_with(tree.path(), &options).unwrap();
+        assert_eq!(paths(&entries), ["b.txt", "other.txt"]);
+    }
+}
+
+#[test]
+fn timings_cover_each_phase() {
+    let tree = TempTree::new();
+    for index in 0..200 {
+        tree.file(
+            &format!("d{}/{}.txt", index % 10, index),
+            &"x".repeat(10_000),
+        );
+    }
+    let (entries, timings) = process_directory_timed(tree.path(), &ProcessOptions::new()).unwrap();
+    assert_eq!(entries.len(), 200);
+    assert!(timings.walking > Duration::ZERO);
+    assert!(timings.metadata > Duration::ZERO);
+    assert!(timings.hashing > Duration::ZERO);
+    let phases = timings.walking + timings.metadata + timings.hashing;
+    assert!(phases <= timings.total, "{:?}", timings);
+    assert!(phases >= timings.total / 4, "{:?}", timings);
+}
+
+#[cfg(unix)]
+#[test]
+fn ownership_records_the_owner() {
+    use std::os::unix::fs::MetadataExt;
+
+    let tree = TempTree::new();
+    tree.file("a.txt", "a");
+    // Files are created owned by the user running the test.
+    let metadata = fs::metadata(tree.path().join("a.txt")).unwrap();
+
+    let options = ProcessOptions::new().with_ownership(true);
+    let entry = &process_directory_with(tree.path(), &options).unwrap()[0];
+    assert_eq!(entry.uid, Some(metadata.uid()));
+    assert_eq!(entry.gid, Some(metadata.gid()));
+    let plain = &process_directory_with(tree.path(), &ProcessOptions::new()).unwrap()[0];
+    assert_eq!((plain.uid, plain.gid), (None, None));
+}
+
+#[test]
+fn cancelling_returns_a_partial_manifest() {
+    let tree = TempTree::new();
+    for index in 0..50 {
+        tree.file(&format!("{}.txt", index), "x");
+    }
+    let cancel = AtomicBool::new(false);
+    let report = process_directory_with_progress(
+        tree.path(),
+        &ProcessOptions::new(),
+        Some(&cancel),
+        |_, processed, _| {
+            if processed == 10 {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        },
+    )
+    .unwrap();
+    assert!(report.interrupted);
+    assert_eq!(report.entries.len(), 10);
+
+    let report =
+        process_directory_cancellable(tree.path(), &ProcessOptions::new(), &cancel).unwrap();
+    assert!(report.interrupted && report.entries.is_empty());
+}
+
+#[test]
+fn max_depth_stops_descending_but_still_ignores() {
+    let tree = TempTree::new();
+    tree.file("top.txt", "0")
+        .file("top.log", "0")
+        .file("one/mid.txt", "1")
+        .file("one/mid.log", "1")
+        .file("one/two/deep.txt", "2")
+        .file("one/two/three/deeper.txt", "3");
+    let options = ProcessOptions::new()
+        .with_ignore(vec!["*.log".to_owned()])
+        .with_max_depth(Some(1));
+    let entries = process_directory_with(tree.path(), &options).unwrap();
+    assert_eq!(paths(&entries), ["one/mid.txt", "top.txt"]);
+
+    let options = options.with_max_depth(Some(0));
+    let entries = process_directory_with(tree.path(), &options).unwrap();
+    assert_eq!(paths(&entries), ["top.txt"]);
+}
+
+#[test]
+fn output_order_does_not_depend_on_creation_order() {
+    let names: Vec<String> = (0..40)
+        .map(|index| format!("d{}/f{}.txt", index % 4, index))
+        .collect();
+    let forward = TempTree::new();
+    for name in &names {
+        forward.file(name, name);
+    }
+    let shuffled = TempTree::new();
+    for index in 0..names.len() {
+        let name = &names[index * 17 % names.len()];
+        shuffled.file(name, name);
+    }
+
+    let options = ProcessOptions::new();
+    let entries = process_directory_with(forward.path(), &options).unwrap();
+    assert_eq!(
+        process_directory_with(shuffled.path(), &options).unwrap(),
+        entries
+    );
+    let mut sorted = paths(&entries);
+    sorted.sort();
+    assert_eq!(paths(&entries), sorted);
+}
+
+#[cfg(unix)]
+#[test]
+fn unreadable_files_are_collected_as_errors() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a")
+        .file("secret.txt", "s")
+        .file("z.txt", "z");
+    if !tree.deny("secret.txt") {
+        return;
+    }
+
+    let options = ProcessOptions::new();
+    let (entries, errors) = process_directory_with_errors(tree.path(), &options).unwrap();
+    assert_eq!(paths(&entries), ["a.txt", "z.txt"]);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].0, tree.path().join("secret.txt"));
+    assert!(process_directory_with(tree.path(), &options).is_err());
+}
+
+#[test]
+fn empty_dirs_are_recorded_when_asked_for() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a")
+        .file("full/b.txt", "b")
+        .dir("empty")
+        .dir("full/nested/empty");
+    let plain = process_directory_with(tree.path(), &ProcessOptions::new()).unwrap();
+    assert_eq!(paths(&plain), ["a.txt", "full/b.txt"]);
+
+    let options = ProcessOptions::new().with_empty_dirs(true);
+    let entries = process_directory_with(tree.path(), &options).unwrap();
+    assert_eq!(
+        paths(&entries),
+        ["a.txt", "empty", "full/b.txt", "full/nested/empty"]
+    );
+    let dirs: Vec<_> = entries.iter().filter(|entry| entry.is_dir).collect();
+    assert_eq!(dirs.len(), 2);
+    assert!(dirs.iter().all(|entry| entry.hash.is_empty()));
+}
+
+#[test]
+fn base_dir_makes_differently_rooted_copies_comparable() {
+    let alice = TempTree::new();
+    let opt = TempTree::new();
+    for tree in [&alice, &opt] {
+        tree.file("project/src/lib.rs", "lib")
+            .file("project/src/bin/main.rs", "main");
+    }
+    let scan = |tree: &TempTree| {
+        let base = tree.path().join("project");
+        let options = ProcessOptions::new().with_base_dir(&base);
+        let serial = process_directory_with(base.join("src"), &options).unwrap();
+        let queued =
+            process_directory_queued(base.join("src"), &options.with_hash_threads(Some(2)))
+                .unwrap();
+        assert_eq!(serial, queued);
+        serial
+    };
+    let entries = scan(&alice);
+    assert_eq!(paths(&entries), ["src/bin/main.rs", "src/lib.rs"]);
+    assert_eq!(scan(&opt), entries);
+}
+
+#[test]
+fn filtered_scan_hashes_only_the_files_the_predicate_keeps() {
+    let tree = TempTree::new();
+    tree.file("small.txt", "s")
+        .file("large.txt", &"x".repeat(100))
+        .file("sub/generated.rs", &"y".repeat(100))
+        .file("sub/.kushnignore", "*.log\n")
+        .file("sub/debug.log", &"z".repeat(100));
+    let options = ProcessOptions::new()
+        .with_nested_ignore(true)
+        .with_max_file_size(Some(50));
+    let entries = process_directory_filtered(tree.path(), &options, |path, metadata| {
+        metadata.len() < 50 || path.ends_with("generated.rs")
+    });
+    assert!(matches!(entries, Err(KushnError::FileTooLarge { .. })));
+
+    let entries = process_directory_filtered(tree.path(), &options, |path, metadata| {
+        assert!(!path.ends_with("debug.log"));
+        metadata.len() < 50 && !path.ends_with(".kushnignore")
+    })
+    .unwrap();
+    assert_eq!(paths(&entries), ["small.txt"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn hashed_link_targets_end_at_symlink_cycles() {
+    let tree = TempTree::new();
+    tree.file("real/a.txt", "a")
+        .symlink("ping", "pong")
+        .symlink("pong", "ping")
+        .symlink("real/loop", "..");
+    let options = ProcessOptions::new().with_symlinks(SymlinkPolicy::HashLinkTarget);
+    let entries = process_directory_with(tree.path(), &options).unwrap();
+    assert_eq!(paths(&entries), ["ping", "pong", "real/a.txt", "real/loop"]);
+    let target = |path: &str| {
+        let entry = entries.iter().find(|entry| entry.path == path).unwrap();
+        entry.link_target.as_deref()
+    };
+    assert_eq!(target("ping"), Some("pong"));
+    assert_eq!(target("real/loop"), Some(".."));
+    assert_eq!(target("real/a.txt"), None);
+}
+
+#[cfg(unix)]
+#[test]
+fn following_a_symlink_cycle_terminates_with_a_loop_warning() {
+    let tree = TempTree::new();
+    tree.file("real/a.txt", "a")
+        .symlink("real/loop", "..")
+        .symlink("real/self", ".");
+    let root = tree.path().to_path_buf();
+    for traversal in [Traversal::DepthFirst, Traversal::BreadthFirst] {
+        let options = ProcessOptions::new()
+            .with_symlinks(SymlinkPolicy::Follow)
+            .with_traversal(traversal);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let scan_root = root.clone();
+        std::thread::spawn(move || {
+            let strict = process_directory_with(&scan_root, &options);
+            let lenient = scan_directory(&scan_root, &options.with_continue_on_error(true));
+            let _ = sender.send((strict, lenient));
+        });
+        let (strict, lenient) = receiver
+            .recv_timeout(Duration::from_secs(30))
+            .unwrap_or_else(|_| panic!("{:?} scan did not terminate", traversal));
+
+        assert!(strict.is_err(), "{:?}", traversal);
+        let report = lenient.unwrap();
+        assert_eq!(paths(&report.entries), ["real/a.txt"], "{:?}", traversal);
+        let mut loops: Vec<_> = report
+            .warnings
+            .iter()
+            .map(|warning| match warning {
+                Warning::SymlinkLoop(path) => path.strip_prefix(&root).unwrap().to_path_buf(),
+                other => panic!("{:?}: unexpected {:?}", traversal, other),
+            })
+            .collect();
+        loops.sort();
+        assert_eq!(loops, [Path::new("real/loop"), Path::new("real/self")]);
+    }
+}
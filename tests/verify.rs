@@ -0,0 +1,127 @@
+mod common;
+
+use std::fs;
+
+use common::TempTree;
+use kushn::{
+    calculate_file_hash, process_directory_with, sort_entries, verify_against_coreutils,
+    verify_and_update, verify_directory, verify_directory_with, ProcessOptions,
+};
+
+#[test]
+fn verify_reports_every_file_of_an_emptied_tree_as_missing() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a").file("sub/b.txt", "b");
+    let options = ProcessOptions::new();
+    let manifest = process_directory_with(tree.path(), &options).unwrap();
+    fs::remove_file(tree.path().join("a.txt")).unwrap();
+    fs::remove_file(tree.path().join("sub/b.txt")).unwrap();
+
+    let report = verify_directory_with(tree.path(), &manifest, &options).unwrap();
+    assert_eq!(report.missing, ["a.txt", "sub/b.txt"]);
+    assert!(report.matched.is_empty() && report.added.is_empty());
+
+    let (report, updated) = verify_and_update(tree.path(), &manifest, &options).unwrap();
+    assert_eq!(report.missing.len(), 2);
+    assert!(updated.is_empty());
+}
+
+#[test]
+fn ignored_files_may_change() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a").file("logs/run.log", "first run");
+    let manifest = process_directory_with(tree.path(), &ProcessOptions::new()).unwrap();
+    tree.file("logs/run.log", "second run")
+        .file("logs/new.log", "new");
+
+    let ignore = ["*.log".to_owned()];
+    let report = verify_directory(tree.path(), &manifest, &ignore).unwrap();
+    assert!(report.is_clean(), "{:?}", report);
+    assert_eq!(report.matched, ["a.txt"]);
+
+    let report = verify_directory(tree.path(), &manifest, &[]).unwrap();
+    assert_eq!(report.changed, ["logs/run.log"]);
+    assert_eq!(report.added, ["logs/new.log"]);
+}
+
+#[test]
+fn coreutils_sums_verify_the_tree() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a").file("sub/b.txt", "b");
+    let sums: String = ["a.txt", "sub/b.txt"]
+        .iter()
+        .map(|path| {
+            let hash = calculate_file_hash(tree.path().join(path)).unwrap();
+            format!("{}  {}\n", hash, path)
+        })
+        .collect();
+    let state = TempTree::new();
+    state.file("SHA256SUMS", &sums);
+    let sums_file = state.path().join("SHA256SUMS");
+
+    let options = ProcessOptions::new();
+    let report = verify_against_coreutils(tree.path(), &sums_file, &options).unwrap();
+    assert!(report.is_clean(), "{:?}", report);
+    assert_eq!(report.matched, ["a.txt", "sub/b.txt"]);
+
+    tree.file("sub/b.txt", "modified");
+    let report = verify_against_coreutils(tree.path(), &sums_file, &options).unwrap();
+    assert_eq!(report.matched, ["a.txt"]);
+    assert_eq!(report.changed, ["sub/b.txt"]);
+}
+
+#[test]
+fn verify_and_update_brings_the_manifest_up_to_date() {
+    let tree = TempTree::new();
+    tree.file("a.txt", "a")
+        .file("edited.txt", "old")
+        .file("removed.txt", "gone");
+    let options = ProcessOptions::new();
+    let manifest = process_directory_with(tree.path(), &options).unwrap();
+    tree.file("edited.txt", "new").file("added.txt", "added");
+    fs::remove_file(tree.path().join("removed.txt")).unwrap();
+
+    let (report, updated) = verify_and_update(tree.path(), &manifest, &options).unwrap();
+    assert_eq!(report.changed, ["edited.txt"]);
+    assert_eq!(report.missing, ["removed.txt"]);
+    assert_eq!(report.added, ["added.txt"]);
+    let edited = updated
+        .iter()
+        .find(|entry| entry.path == "edited.txt")
+        .unwrap();
+    assert_eq!(
+        edited.hash,
+        calculate_file_hash(tree.path().join("edited.txt")).unwrap()
+    );
+
+    let mut updated = updated;
+    sort_entries(&mut updated);
+    assert_eq!(
+        updated,
+        process_directory_with(tree.path(), &options).unwrap()
+    );
+}
+
+#[test]
+fn case_insensitive_paths_match_differently_cased_entries() {
+    let tree = TempTree::new();
+    tree.file("README.md", "readme").file("Src/Lib.rs", "lib");
+    let options = ProcessOptions::new();
+    let mut manifest = process_directory_with(tree.path(), &options).unwrap();
+    for entry in &mut manifest {
+        entry.path = entry.path.to_lowercase();
+    }
+
+    let report = verify_directory_with(tree.path(), &manifest, &options).unwrap();
+    assert_eq!(report.missing, ["readme.md", "src/lib.rs"]);
+    assert_eq!(report.added.len(), 2);
+
+    let options = options.with_case_insensitive_paths(true);
+    let report = verify_directory_with(tree.path(), &manifest, &options).unwrap();
+    assert!(report.is_clean(), "{:?}", report);
+    assert_eq!(report.matched.len(), 2);
+
+    tree.file("Src/Lib.rs", "edited");
+    let report = verify_directory_with(tree.path(), &manifest, &options).unwrap();
+    assert_eq!(report.changed.len(), 1);
+}